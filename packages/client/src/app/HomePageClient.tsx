@@ -2,10 +2,10 @@
 
 import React, { useState, useEffect } from 'react';
 import SelfQRcodeWrapper, { countries, SelfApp, SelfAppBuilder } from '@selfxyz/qrcode';
-import { v4 as uuidv4 } from 'uuid';
 import { logo } from '../../public/logo';
 import { useRouter } from 'next/navigation';
-import { addPODPCD } from './utils';
+import { addPODPCD, selfUserId } from './utils';
+import { HYLE_IDENTITY } from './constants';
 import { convertProofForPOD } from './utils/podConverter';
 import { VerificationData } from './types';
 
@@ -26,12 +26,11 @@ export default function HomePageClient() {
 
   useEffect(() => {
     if (typeof window !== 'undefined') {
-      let storedUserId = sessionStorage.getItem('userId');
-      if (!storedUserId) {
-        storedUserId = uuidv4();
-        sessionStorage.setItem('userId', storedUserId);
-      }
-      setUserId(storedUserId);
+      // The ticket contract only accepts proofs generated for the identity buying the ticket.
+      selfUserId(HYLE_IDENTITY).then((id) => {
+        sessionStorage.setItem('userId', id);
+        setUserId(id);
+      });
 
       // Check if user is authenticated
       const authData = sessionStorage.getItem('userAuth');
@@ -2,6 +2,7 @@
 
 import { useEffect, useState } from 'react';
 import { useRouter, useSearchParams } from 'next/navigation';
+import { HYLE_IDENTITY } from '../constants';
 
 export default function Buy() {
   const [message, setMessage] = useState<string | null>(null);
@@ -10,6 +11,7 @@ export default function Buy() {
   const [isAuthenticated, setIsAuthenticated] = useState(false);
   const [checkingAuth, setCheckingAuth] = useState(true);
   const [userProfile, setUserProfile] = useState<any>(null);
+  const [passport, setPassport] = useState<any>(null);
   const [accountBalance, setAccountBalance] = useState<number>(0);
   const [ticketInfo, setTicketInfo] = useState<{
     name: string;
@@ -25,6 +27,13 @@ export default function Buy() {
   const [transactionId, setTransactionId] = useState<string | null>(null);
   const [processingStep, setProcessingStep] = useState(0);
   const [paymentMethod, setPaymentMethod] = useState<'balance' | 'card'>('balance');
+  // Price of one ticket as the contract will charge it, quoted by the server
+  const [quote, setQuote] = useState<{
+    tier: string;
+    base_price: number;
+    rate: number;
+    price: number;
+  } | null>(null);
   
   const router = useRouter();
   const searchParams = useSearchParams();
@@ -61,6 +70,7 @@ export default function Buy() {
           if (parsedAuth.isAuthenticated) {
             setIsAuthenticated(true);
             setUserProfile(parsedAuth.profile);
+            setPassport(parsedAuth.passport);
             setAccountBalance(parsedAuth.balance || 0);
             setCheckingAuth(false);
           } else {
@@ -78,25 +88,35 @@ export default function Buy() {
     }
   }, [router, searchParams]);
 
+  // Fetch the price the contract will charge, discounts included
+  useEffect(() => {
+    if (!ticketInfo || !passport) return;
+
+    fetch('http://localhost:4000/api/quote', {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({
+        event_id: searchParams.get('event') || 'default',
+        passport
+      })
+    })
+      .then(async response => {
+        if (!response.ok) {
+          throw new Error(await response.text() || `status ${response.status}`);
+        }
+        setQuote(await response.json());
+      })
+      .catch(e => {
+        console.error('Error fetching quote:', e);
+        setQuote(null);
+        setError(`Could not price the ticket: ${e instanceof Error ? e.message : 'Unknown error'}`);
+      });
+  }, [ticketInfo, passport, searchParams]);
+
   // Calculate price
   const calculatePrice = (): number => {
-    if (!ticketInfo) return 0;
-    const basePrice = parseFloat(ticketInfo.price.replace(/[^0-9.-]+/g, ''));
-    
-    // Apply nationality-based discounts
-
-    let discountedPrice = basePrice;
-    if (userProfile && userProfile.nationality) {
-      if (userProfile.nationality === 'FRA') {
-        // 10% discount for French nationality
-        discountedPrice = basePrice * 0.9;
-      } else if (userProfile.nationality === 'TWN') {
-        // 20% discount for Taiwanese nationality
-        discountedPrice = basePrice * 0.8;
-      }
-    }
-    
-    return discountedPrice * quantity;
+    if (!quote) return 0;
+    return quote.price * quantity;
   };
 
   // Purchase ticket function
@@ -106,6 +126,11 @@ export default function Buy() {
       return;
     }
 
+    if (!passport) {
+      setError('No passport proof found, please sign in again');
+      return;
+    }
+
     setIsLoading(true);
     setMessage('Processing your purchase...');
     setError(null);
@@ -120,21 +145,19 @@ export default function Buy() {
       
       setProcessingStep(3); // Finalizing transaction
       
-      // Make the actual API call to buy the ticket
-      const totalPrice = Math.round(calculatePrice() * 10);
-      console.log(userProfile?.nationality)
+      // Make the actual API call to buy the ticket, the server quotes the price itself
       const response = await fetch('http://localhost:4000/api/buy', {
         method: 'POST',
         headers: {
           'Content-Type': 'application/json',
-          'x-user': 'faucet.hydentity',
+          'x-user': HYLE_IDENTITY,
           'x-session-key': 'your_session_key',
           'x-request-signature': 'your_signature'
         },
         body: JSON.stringify({
+          event_id: searchParams.get('event') || 'default',
           nonce: 1,
-          price: totalPrice,
-          nationality: userProfile?.nationality || 'USA'
+          passport
         })
       });
       
@@ -329,103 +352,10 @@ export default function Buy() {
                                     <path strokeLinecap="round" strokeLinejoin="round" strokeWidth="2" d="M13 16h-1v-4h-1m1-4h.01M21 12a9 9 0 11-18 0 9 9 0 0118 0z"></path>
                                   </svg>
                                   Get a discount based on your nationality
-                                  {userProfile && userProfile.nationality === 'FRA' && (
-                                    <span className="ml-1 text-green-300 font-medium">(10% off for France!)</span>
-                                  )}
-                                  {userProfile && userProfile.nationality === 'TWN' && (
-                                    <span className="ml-1 text-green-300 font-medium">(20% off for Taiwan!)</span>
-                                  )}
-                                </li>
-                                
-                              </ul>
-                            </div>
-                          </div>
-                        </div>
-                      </div>
-                    </div>
-                    
-                    {/* Checkout Panel - Right Column */}
-                    <div className="lg:col-span-1">
-                      <div className="bg-white/10 backdrop-blur-lg p-6 rounded-2xl shadow-2xl border border-white/10 sticky top-6">
-                        <h2 className="text-xl font-semibold mb-4">Order Summary</h2>
-                        
-                        {/* Quantity Selector */}
-                        <div className="mb-6">
-                          <label className="block text-sm font-medium text-gray-300 mb-2">Quantity</label>
-                          <div className="flex">
-                            <button 
-                              onClick={() => handleQuantityChange(quantity - 1)}
-                              disabled={quantity <= 1}
-                              className="px-3 py-2 bg-white/5 border border-white/10 rounded-l-lg hover:bg-white/10 disabled:opacity-50 disabled:cursor-not-allowed"
-                            >
-                              −
-                            </button>
-                            <div className="px-4 py-2 bg-white/5 border-t border-b border-white/10 flex items-center justify-center min-w-[3rem]">
-                              {quantity}
-                            </div>
-                            <button 
-                              onClick={() => handleQuantityChange(quantity + 1)}
-                              disabled={quantity >= 10}
-                              className="px-3 py-2 bg-white/5 border border-white/10 rounded-r-lg hover:bg-white/10 disabled:opacity-50 disabled:cursor-not-allowed"
-                            >
-                              +
-                            </button>
-                          </div>
-                        </div>
-                        
-                        {/* Payment Method */}
-                        <div className="mb-6">
-                          <label className="block text-sm font-medium text-gray-300 mb-2">Payment Method</label>
-                          <div className="grid grid-cols-2 gap-2">
-                            <button 
-                              onClick={() => setPaymentMethod('balance')}
-                              className={`p-3 border rounded-lg flex flex-col items-center justify-center text-sm ${
-                                paymentMethod === 'balance' 
-                                  ? 'border-blue-400 bg-blue-500/10' 
-                                  : 'border-white/10 bg-white/5 hover:bg-white/10'
-                              }`}
-                            >
-                              <svg className="w-5 h-5 mb-1" fill="none" stroke="currentColor" viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg">
-                                <path strokeLinecap="round" strokeLinejoin="round" strokeWidth="2" d="M9 8h6m-5 0a3 3 0 110 6H9l3 3m-3-6h6m6 1a9 9 0 11-18 0 9 9 0 0118 0z"></path>
-                              </svg>
-                              Hylé Balance
-                            </button>
-                            <button 
-                              onClick={() => setPaymentMethod('card')}
-                              className={`p-3 border rounded-lg flex flex-col items-center justify-center text-sm ${
-                                paymentMethod === 'card' 
-                                  ? 'border-blue-400 bg-blue-500/10' 
-                                  : 'border-white/10 bg-white/5 hover:bg-white/10'
-                              }`}
-                            >
-                              <svg className="w-5 h-5 mb-1" fill="none" stroke="currentColor" viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg">
-                                <path strokeLinecap="round" strokeLinejoin="round" strokeWidth="2" d="M3 10h18M7 15h1m4 0h1m-7 4h12a3 3 0 003-3V8a3 3 0 00-3-3H6a3 3 0 00-3 3v8a3 3 0 003 3z"></path>
-                              </svg>
-                              Credit Card
-                            </button>
-                          </div>
-                        </div>
-                        
-                        {/* Price Breakdown */}
-                        <div className="border-t border-white/10 pt-4 mb-6">
-                          <div className="flex justify-between mb-2">
-                            <span className="text-gray-300">Ticket Price</span>
-                            <span>${parseFloat(ticketInfo.price.replace(/[^0-9.-]+/g, '')).toFixed(2)}</span>
-                          </div>
-                          <div className="flex justify-between mb-2">
-                            <span className="text-gray-300">Quantity</span>
-                            <span>× {quantity}</span>
-                          </div>
-                          {userProfile && userProfile.nationality === 'FRA' && (
-                            <div className="flex justify-between mb-2 text-green-300">
-                              <span>Regional Pricing Discount</span>
-                              <span>-10%</span>
-                            </div>
-                          )}
-                          {userProfile && userProfile.nationality === 'TWN' && (
+                                  {quote && quote.rate < 10000 && (
                             <div className="flex justify-between mb-2 text-green-300">
                               <span>Regional Pricing Discount</span>
-                              <span>-20%</span>
+                              <span>-{(10000 - quote.rate) / 100}%</span>
                             </div>
                           )}
                           {paymentMethod === 'card' && (
@@ -452,7 +382,7 @@ export default function Buy() {
                         {/* Purchase Button */}
                         <button
                           onClick={purchaseTicket}
-                          disabled={isLoading || (paymentMethod === 'balance' && calculatePrice() > accountBalance)}
+                          disabled={isLoading || !quote || (paymentMethod === 'balance' && calculatePrice() > accountBalance)}
                           className="w-full px-6 py-4 bg-gradient-to-r from-blue-500 to-purple-500 rounded-lg text-lg font-medium hover:from-blue-600 hover:to-purple-600 transition-all disabled:opacity-50 disabled:cursor-not-allowed relative overflow-hidden"
                         >
                           {isLoading ? (
@@ -24,3 +24,6 @@ export const ZUPASS_SERVER_URL = ZUPASS_SERVER_URL_ENV
   : IS_PROD
     ? 'https://api.zupass.org/'
     : 'http://localhost:3002/';
+
+// Hyle identity tickets are bought with, Self proofs are generated for it.
+export const HYLE_IDENTITY = 'faucet.hydentity';
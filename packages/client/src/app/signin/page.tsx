@@ -3,6 +3,7 @@
 import { useEffect, useState, useRef } from 'react';
 import { useZupassPopupMessages } from '@pcd/passport-interface';
 import { getPCDFromZupass } from '../utils';
+import { convertPODToProof } from '../utils/podConverter';
 import { PODPCDPackage } from '@pcd/pod-pcd';
 import { useRouter } from 'next/navigation';
 
@@ -25,7 +26,7 @@ export default function SignIn() {
       console.log('Parsed data:', parsed);
 
       // Create a formatted user profile from the data
-      let formattedUser = {
+      let formattedUser: any = {
         type: parsed.type,
         isAuthenticated: true,
         profile: {
@@ -42,11 +43,18 @@ export default function SignIn() {
           if (pcdData.jsonPOD?.entries) {
             const entries = pcdData.jsonPOD.entries;
             
+            // The server needs the passport proof itself to buy tickets
+            const { proof, publicSignals } = convertPODToProof(entries);
+
             formattedUser = {
               ...formattedUser,
               profile: {
                 name: entries.disclosure_name || 'Unknown',
                 nationality: entries.disclosure_nationality || 'Unknown',
+              },
+              passport: {
+                proof,
+                public_signal: publicSignals,
               }
             };
           }
@@ -61,6 +69,7 @@ export default function SignIn() {
       sessionStorage.setItem('userAuth', JSON.stringify({
         isAuthenticated: true,
         profile: formattedUser.profile,
+        passport: formattedUser.passport,
         balance: 250, // Fixed balance of 250
         timestamp: new Date().toISOString()
       }));
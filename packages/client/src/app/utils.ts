@@ -10,6 +10,26 @@ import { ZUPASS_URL } from './constants';
 import { useEffect, useState, useMemo } from 'react';
 import { useRouter } from 'next/navigation';
 
+/**
+ * Self user id of the proofs generated for `identity`, which the ticket contract checks.
+ * First 16 bytes of the SHA-256 of the identity with the bits of a UUIDv8, like
+ * `user_identifier` in the contract.
+ */
+export async function selfUserId(identity: string): Promise<string> {
+  const hash = await crypto.subtle.digest('SHA-256', new TextEncoder().encode(identity));
+  const bytes = new Uint8Array(hash).slice(0, 16);
+  bytes[6] = (bytes[6] & 0x0f) | 0x80;
+  bytes[8] = (bytes[8] & 0x3f) | 0x80;
+  const hex = Array.from(bytes, (b) => b.toString(16).padStart(2, '0')).join('');
+  return [
+    hex.slice(0, 8),
+    hex.slice(8, 12),
+    hex.slice(12, 16),
+    hex.slice(16, 20),
+    hex.slice(20),
+  ].join('-');
+}
+
 // Storage key for Zupass data in localStorage
 const ZUPASS_DATA_KEY = 'zupass_pcd_data';
 const ZUPASS_REDIRECT_KEY = 'zupass_redirect_path';
@@ -0,0 +1,188 @@
+//! Generates the country tables of `constants.rs` from `data/countries.txt`, and the
+//! migration parameters of `state.rs` from `data/migration.txt`.
+//!
+//! Tables are sorted static arrays, so the guest looks countries up with a binary search
+//! instead of building hash sets on every proof.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::Path;
+
+const DATA: &str = "data/countries.txt";
+
+const MIGRATION: &str = "data/migration.txt";
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", DATA);
+
+    let data = std::fs::read_to_string(DATA).expect("failed to read the country data");
+
+    let mut names = BTreeMap::new();
+    let mut aliases = BTreeMap::new();
+    let mut groups: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+
+    for (number, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(';').map(str::trim).collect();
+        let [code, name, members, alias_list] = fields[..] else {
+            panic!("{}:{}: expected code;name;groups;aliases", DATA, number + 1);
+        };
+
+        if code.len() != 3 || !code.bytes().all(|b| b.is_ascii_uppercase()) {
+            panic!("{}:{}: {} is not an alpha-3 code", DATA, number + 1, code);
+        }
+        if names.insert(code, name).is_some() {
+            panic!("{}:{}: {} is listed twice", DATA, number + 1, code);
+        }
+
+        for group in members.split_whitespace() {
+            groups.entry(group).or_default().insert(code);
+        }
+        for alias in alias_list
+            .split('|')
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+        {
+            if aliases.insert(alias, code).is_some() {
+                panic!("{}:{}: alias {} is listed twice", DATA, number + 1, alias);
+            }
+        }
+    }
+
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "/// Alpha-3 code and name of every country, sorted by code."
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub static COUNTRIES: [(&str, &str); {}] = [",
+        names.len()
+    )
+    .unwrap();
+    for (code, name) in &names {
+        writeln!(out, "    ({:?}, {:?}),", code, name).unwrap();
+    }
+    writeln!(out, "];\n").unwrap();
+
+    writeln!(
+        out,
+        "/// Other names and codes of countries, with their alpha-3 code, sorted by alias."
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub static ALIASES: [(&str, &str); {}] = [",
+        aliases.len()
+    )
+    .unwrap();
+    for (alias, code) in &aliases {
+        writeln!(out, "    ({:?}, {:?}),", alias, code).unwrap();
+    }
+    writeln!(out, "];\n").unwrap();
+
+    for (group, codes) in &groups {
+        writeln!(out, "/// Alpha-3 codes of the {} group, sorted.", group).unwrap();
+        writeln!(
+            out,
+            "pub static {}_COUNTRIES: [&str; {}] = {:?};\n",
+            group,
+            codes.len(),
+            codes.iter().collect::<Vec<_>>()
+        )
+        .unwrap();
+    }
+
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    std::fs::write(Path::new(&out_dir).join("countries.rs"), out)
+        .expect("failed to write the country tables");
+
+    write_migration(&out_dir);
+}
+
+/// Pins the parameters legacy states are migrated with in the guest, so that whoever proves
+/// the migration cannot choose them. They are read from a file of the repository rather than
+/// the environment, so that the reproducible build sees them too.
+fn write_migration(out_dir: &str) {
+    println!("cargo:rerun-if-changed={}", MIGRATION);
+
+    let data = std::fs::read_to_string(MIGRATION).expect("failed to read the migration data");
+
+    let mut params = BTreeMap::new();
+    for (number, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once(';') else {
+            panic!("{}:{}: expected key;value", MIGRATION, number + 1);
+        };
+        let key = key.trim();
+        if !["owner", "passport_vk", "passport_scope"].contains(&key) {
+            panic!("{}:{}: unknown parameter {}", MIGRATION, number + 1, key);
+        }
+        if params.insert(key, value.trim()).is_some() {
+            panic!("{}:{}: {} is listed twice", MIGRATION, number + 1, key);
+        }
+    }
+
+    let owner = params.get("owner");
+    let passport_vk = params.get("passport_vk").map(|file| {
+        let path = Path::new(MIGRATION).with_file_name(file);
+        println!("cargo:rerun-if-changed={}", path.display());
+        std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("failed to read the passport vk {}: {}", path.display(), e))
+    });
+    let passport_scope = params.get("passport_scope");
+
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "/// Owner of migrated legacy states, from `{}`.",
+        MIGRATION
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub const MIGRATION_OWNER: Option<&str> = {:?};\n",
+        owner
+    )
+    .unwrap();
+
+    writeln!(
+        out,
+        "/// JSON verification key of migrated legacy states, read from the file named in `{}`.",
+        MIGRATION
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub const MIGRATION_PASSPORT_VK: Option<&str> = {:?};\n",
+        passport_vk
+    )
+    .unwrap();
+
+    writeln!(
+        out,
+        "/// Passport scope of migrated legacy states, as a decimal field element, from `{}`.",
+        MIGRATION
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub const MIGRATION_PASSPORT_SCOPE: Option<&str> = {:?};",
+        passport_scope
+    )
+    .unwrap();
+
+    std::fs::write(Path::new(out_dir).join("migration.rs"), out)
+        .expect("failed to write the migration parameters");
+}
@@ -1,9 +1,20 @@
 use clap::{Parser, Subcommand};
+use client_sdk::rest_client::NodeApiHttpClient;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{SystemTime, UNIX_EPOCH};
 use ticket_app::client::tx_executor_handler::metadata::PROGRAM_ID;
+use ticket_app::countries::Country;
+use ticket_app::eligibility::Eligibility;
+use ticket_app::event::{parse_price, Event, PriceList, PriceTier, SaleBound, SaleWindow};
+use ticket_app::frontend_data::{Groth16Proof, PassportData};
+use ticket_app::policy::DiscountRule;
+use ticket_app::pricing::quote;
+use ticket_app::public_signals::{parse_field_element, PassportSignals};
 use ticket_app::TicketApp;
 use ticket_app::TicketAppAction;
 use sdk::api::APIRegisterContract;
-use sdk::{BlobTransaction, ZkContract};
+use sdk::{BlobIndex, BlobTransaction, ContractAction, ContractName, TimestampMs, TxContext, ZkContract};
+use hyle_hydentity::HydentityAction;
 use hyle_hyllar::HyllarAction;
 
 #[derive(Parser)]
@@ -19,6 +30,10 @@ struct Cli {
     #[arg(long, default_value = "http://localhost:4321")]
     pub host: String,
 
+    /// App server handing passport proofs to the prover
+    #[arg(long, default_value = "http://localhost:4000")]
+    pub server: String,
+
     #[arg(long, default_value = "ticket-app")]
     pub contract_name: String,
 
@@ -28,15 +43,141 @@ struct Cli {
     #[arg(long, default_value = "10")]
     pub ticket_price: u128,
 
+    /// Price in another token the tickets can be paid with, as `token=amount`
+    #[arg(long, value_parser = parse_price)]
+    pub extra_price: Vec<(ContractName, u128)>,
+
     #[arg(long, default_value = "bob.ticket-app")]
     pub id: String,
+
+    /// Identity contract verifying `id`
+    #[arg(long, default_value = "hydentity")]
+    pub identity_contract_name: String,
+
+    #[arg(long, default_value = "0")]
+    pub nonce: u32,
+
+    #[arg(long, default_value = "default")]
+    pub event_id: String,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    Register {},
-    BuyTicket,
+    Register {
+        /// snarkjs verification key of the passport disclosure circuit
+        #[arg(long)]
+        passport_vk: String,
+
+        /// Scope passport proofs are generated for, as a decimal field element
+        #[arg(long, value_parser = parse_field_element)]
+        passport_scope: [u8; 32],
+
+        /// Number of tickets on sale
+        #[arg(long, default_value = "100")]
+        capacity: u32,
+
+        /// Minimum age buyers must prove
+        #[arg(long)]
+        min_age: Option<u8>,
+
+        /// Block height at which sales open
+        #[arg(long)]
+        sale_start: Option<u64>,
+
+        /// Block height at which sales close
+        #[arg(long)]
+        sale_end: Option<u64>,
+
+        /// Price of the tickets sold before `early_bird_end`
+        #[arg(long, requires = "early_bird_end")]
+        early_bird_price: Option<u128>,
+
+        #[arg(long)]
+        early_bird_end: Option<u64>,
+
+        /// Block height until which the proceeds are held in escrow, not before `sale_end`
+        #[arg(long, requires = "sale_end")]
+        escrow_release: Option<u64>,
+    },
+    BuyTicket {
+        /// JSON file of the buyer's passport proof and public signals, as Self returns them
+        #[arg(long)]
+        passport: String,
+
+        /// Account paying for the ticket, which approved the buyer to spend on its behalf
+        #[arg(long)]
+        sponsor: Option<String>,
+    },
     HasTicket,
+    SpendTicket {
+        #[arg(long)]
+        ticket_id: u64,
+    },
+    TransferTicket {
+        #[arg(long)]
+        ticket_id: u64,
+
+        #[arg(long)]
+        recipient: String,
+
+        /// JSON file of the recipient's passport proof and public signals, as Self returns them.
+        /// Required when the event restricts its holders
+        #[arg(long)]
+        recipient_passport: Option<String>,
+
+        /// Amount repaid when the recipient is not eligible for the buyer's discount
+        #[arg(long, default_value = "0")]
+        clawback: u128,
+    },
+    SetPrice,
+    AddEvent {
+        /// Number of tickets on sale
+        #[arg(long, default_value = "100")]
+        capacity: u32,
+
+        /// Minimum age buyers must prove
+        #[arg(long)]
+        min_age: Option<u8>,
+    },
+    RemoveEvent,
+    SetIdentityContracts {
+        /// Contracts trusted to verify the identity of callers
+        #[arg(long, required = true)]
+        identity_contracts: Vec<String>,
+    },
+    SetDenylist {
+        /// Nationalities tickets cannot be sold to, as alpha-3 codes or names
+        #[arg(long)]
+        denylist: Vec<Country>,
+    },
+    CancelEvent,
+    ClaimRefund {
+        #[arg(long)]
+        ticket_id: u64,
+
+        /// Amount paid for the ticket
+        #[arg(long)]
+        amount: u128,
+    },
+    Withdraw {
+        #[arg(long)]
+        amount: u128,
+
+        /// Identity the proceeds are paid to
+        #[arg(long)]
+        recipient: String,
+    },
+    SetDiscountRules {
+        /// JSON list of discount rules, checked in order
+        #[arg(long)]
+        rules: String,
+    },
+    SetEligibility {
+        /// Nationalities allowed to buy tickets, e.g. "Region(EEA) & !Country(CHE) | Country(TWN)".
+        /// Lifts the restriction when omitted
+        #[arg(long)]
+        eligibility: Option<Eligibility>,
+    },
 }
 
 #[tokio::main]
@@ -47,18 +188,59 @@ async fn main() {
         .init();
 
     let cli = Cli::parse();
+    let ticket_prices = prices(&cli);
+
+    // Proves the identity of the sender. Appended last, so the indexes of the other blobs
+    // do not move.
+    let identity_blob = HydentityAction::VerifyIdentity {
+        account: cli.id.clone(),
+        nonce: cli.nonce,
+    }
+    .as_blob(cli.identity_contract_name.clone().into());
 
-    let client = client_sdk::rest_client::NodeApiHttpClient::new(cli.host).unwrap();
+    let client = NodeApiHttpClient::new(cli.host).unwrap();
 
     let contract_name = &cli.contract_name;
     let token_contract_name = &cli.token_contract_name;
 
     match cli.command {
-        Commands::Register {} => {
+        Commands::Register {
+            passport_vk,
+            passport_scope,
+            capacity,
+            min_age,
+            sale_start,
+            sale_end,
+            early_bird_price,
+            early_bird_end,
+            escrow_release,
+        } => {
+            let passport_vk = std::fs::read_to_string(passport_vk).unwrap();
+
             // Build initial state of contract
+            let mut event = Event::new(ticket_prices, capacity, min_age);
+            event.sale_window = SaleWindow {
+                start: sale_start.map(SaleBound::BlockHeight),
+                end: sale_end.map(SaleBound::BlockHeight),
+            };
+            event.escrow_release = escrow_release.map(SaleBound::BlockHeight);
+            event.check_escrow().unwrap();
+            if let (Some(price), Some(end)) = (early_bird_price, early_bird_end) {
+                event.price_tiers.push(PriceTier {
+                    name: "early-bird".to_string(),
+                    window: SaleWindow {
+                        start: None,
+                        end: Some(SaleBound::BlockHeight(end)),
+                    },
+                    prices: BTreeMap::from([(token_contract_name.clone().into(), price)]),
+                });
+            }
             let initial_state = TicketApp::new(
-                vec![],
-                (token_contract_name.clone().into(), cli.ticket_price),
+                cli.id.clone().into(),
+                BTreeMap::from([(cli.event_id.clone(), event)]),
+                serde_json::from_str(&passport_vk).unwrap(),
+                passport_scope,
+                BTreeSet::from([cli.identity_contract_name.clone().into()]),
             );
             println!("Initial state: {:?}", initial_state);
 
@@ -74,49 +256,246 @@ async fn main() {
                 .unwrap();
             println!("✅ Register contract tx sent. Tx hash: {}", res);
         }
-        Commands::BuyTicket => {
-            // Create the token transfer action for payment
-            let transfer_action = HyllarAction::Transfer {
-                recipient: contract_name.clone().into(),
-                amount: cli.ticket_price,
-            };
+        Commands::BuyTicket { passport, sponsor } => {
+            let passport = read_passport(&passport);
+            let signals = PassportSignals::decode(&passport.public_signal).unwrap();
+            println!("Passport signals: {:?}", signals);
 
-            // Create the buy ticket action
-            let buy_action = TicketAppAction::BuyTicket;
+            // Quote the ticket price from the current contract state
+            let contract = client.get_contract(&contract_name.clone().into()).await.unwrap();
+            let state = match TicketApp::decode(&contract.state.0) {
+                Ok(state) => state,
+                Err(e) => {
+                    eprintln!("❌ Could not decode the contract state: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let event = state.events.get(&cli.event_id).expect("event does not exist");
+            let tx_ctx = TxContext {
+                block_height: client.get_block_height().await.unwrap(),
+                timestamp: TimestampMs(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()),
+                ..Default::default()
+            };
+            let quote = quote(signals.nationality, event, &token_contract_name.clone().into(), &tx_ctx).unwrap();
+            println!("Quote: {:?}", quote);
 
-            // Build the blob transaction with both actions
-            let blobs = vec![
-                sdk::Blob {
-                    contract_name: contract_name.clone().into(),
-                    data: sdk::BlobData(borsh::to_vec(&buy_action).expect("failed to encode BuyTicket action")),
+            // Create the token transfer action for payment
+            let transfer_action = match sponsor {
+                Some(owner) => HyllarAction::TransferFrom {
+                    owner,
+                    recipient: contract_name.clone().into(),
+                    amount: quote.price,
                 },
-                sdk::Blob {
-                    contract_name: token_contract_name.clone().into(),
-                    data: sdk::BlobData(borsh::to_vec(&transfer_action).expect("failed to encode Transfer action")),
+                None => HyllarAction::Transfer {
+                    recipient: contract_name.clone().into(),
+                    amount: quote.price,
                 },
-            ];
-            
-            let blob_tx = BlobTransaction::new(cli.id.clone(), blobs.clone());
+            };
 
-            // Send the blob transaction
-            let blob_tx_hash = client.send_tx_blob(&blob_tx).await.unwrap();
-            println!("✅ Blob tx sent. Tx hash: {}", blob_tx_hash);
+            // Create the buy ticket action, the proof itself is a private input of the prover
+            let buy_action = TicketAppAction::BuyTicket {
+                event_id: cli.event_id.clone(),
+                public_signals: passport.public_signal,
+            };
+
+            // Send the blob transaction with both actions through the server, which hands the proof to the prover
+            let transfer_blob = transfer_action.as_blob(token_contract_name.clone().into(), None, None);
+            send_with_proof(&cli.server, cli.id.clone(), contract_name, &buy_action, vec![transfer_blob, identity_blob.clone()], &passport.proof).await;
         }
         Commands::HasTicket => {
             // Create the check ticket action
-            let action = TicketAppAction::HasTicket;
-            
-            // Build the blob transaction
-            let blobs = vec![sdk::Blob {
-                contract_name: contract_name.clone().into(),
-                data: sdk::BlobData(borsh::to_vec(&action).expect("failed to encode HasTicket action")),
-            }];
+            let action = TicketAppAction::HasTicket {
+                event_id: cli.event_id.clone(),
+            };
             
-            let blob_tx = BlobTransaction::new(cli.id.clone(), blobs.clone());
+            // Send the blob transaction, reading the state needs no identity proof
+            send_action(&client, cli.id.clone(), contract_name, &action, vec![]).await;
+        }
+        Commands::TransferTicket {
+            ticket_id,
+            recipient,
+            recipient_passport,
+            clawback,
+        } => {
+            let recipient_passport = recipient_passport.as_deref().map(read_passport);
+
+            // Create the transfer ticket action, the recipient's proof is a private input of the prover
+            let action = TicketAppAction::TransferTicket {
+                ticket_id,
+                recipient: recipient.into(),
+                recipient_passport: recipient_passport.as_ref().map(|passport| passport.public_signal.clone()),
+            };
+
+            // Create the token transfer action repaying the discount, only when one is due
+            let mut blobs = vec![identity_blob.clone()];
+            if clawback > 0 {
+                let transfer_action = HyllarAction::Transfer {
+                    recipient: contract_name.clone(),
+                    amount: clawback,
+                };
+                blobs.insert(0, transfer_action.as_blob(token_contract_name.clone().into(), None, None));
+            }
+
+            // Send the blob transaction, through the server when the proof has to reach the prover
+            match recipient_passport {
+                Some(passport) => send_with_proof(&cli.server, cli.id.clone(), contract_name, &action, blobs, &passport.proof).await,
+                None => send_action(&client, cli.id.clone(), contract_name, &action, blobs).await,
+            }
+        }
+        Commands::SetPrice => {
+            // Create the set price action, only the owner may send it
+            let action = TicketAppAction::SetPrice {
+                event_id: cli.event_id.clone(),
+                prices: ticket_prices,
+            };
+
+            // Send the blob transaction
+            send_action(&client, cli.id.clone(), contract_name, &action, vec![identity_blob.clone()]).await;
+        }
+        Commands::AddEvent { capacity, min_age } => {
+            // Create the add event action, only the owner may send it
+            let action = TicketAppAction::AddEvent {
+                event_id: cli.event_id.clone(),
+                event: Box::new(Event::new(ticket_prices, capacity, min_age)),
+            };
+
+            // Send the blob transaction
+            send_action(&client, cli.id.clone(), contract_name, &action, vec![identity_blob.clone()]).await;
+        }
+        Commands::RemoveEvent => {
+            // Create the remove event action, only the owner may send it
+            let action = TicketAppAction::RemoveEvent {
+                event_id: cli.event_id.clone(),
+            };
+
+            // Send the blob transaction
+            send_action(&client, cli.id.clone(), contract_name, &action, vec![identity_blob.clone()]).await;
+        }
+        Commands::SetIdentityContracts { identity_contracts } => {
+            // Create the set identity contracts action, only the owner may send it
+            let action = TicketAppAction::SetIdentityContracts {
+                identity_contracts: identity_contracts.into_iter().map(Into::into).collect(),
+            };
+
+            // Send the blob transaction
+            send_action(&client, cli.id.clone(), contract_name, &action, vec![identity_blob.clone()]).await;
+        }
+        Commands::SetDenylist { denylist } => {
+            // Create the set denylist action, only the owner may send it
+            let action = TicketAppAction::SetDenylist {
+                denylist: denylist.into_iter().collect(),
+            };
+
+            // Send the blob transaction
+            send_action(&client, cli.id.clone(), contract_name, &action, vec![identity_blob.clone()]).await;
+        }
+        Commands::CancelEvent => {
+            // Create the cancel event action, only the owner may send it
+            let action = TicketAppAction::CancelEvent {
+                event_id: cli.event_id.clone(),
+            };
+
+            // Send the blob transaction
+            send_action(&client, cli.id.clone(), contract_name, &action, vec![identity_blob.clone()]).await;
+        }
+        Commands::ClaimRefund { ticket_id, amount } => {
+            // Create the claim refund action
+            let action = TicketAppAction::ClaimRefund { ticket_id };
+
+            // Create the token transfer paying the refund out of the contract
+            let transfer_action = HyllarAction::Transfer {
+                recipient: cli.id.clone(),
+                amount,
+            };
+
+            // Send the blob transaction, the ticket app blob is the caller of the transfer
+            let transfer_blob = transfer_action.as_blob(token_contract_name.clone().into(), Some(BlobIndex(0)), None);
+            send_action(&client, cli.id.clone(), contract_name, &action, vec![transfer_blob, identity_blob.clone()]).await;
+        }
+        Commands::Withdraw { amount, recipient } => {
+            // Create the withdraw action, only the owner may send it
+            let action = TicketAppAction::Withdraw {
+                event_id: cli.event_id.clone(),
+                token: token_contract_name.clone().into(),
+                amount,
+                recipient: recipient.clone().into(),
+            };
+
+            // Create the token transfer paying the proceeds out of the contract
+            let transfer_action = HyllarAction::Transfer { recipient, amount };
+
+            // Send the blob transaction, the ticket app blob is the caller of the transfer
+            let transfer_blob = transfer_action.as_blob(token_contract_name.clone().into(), Some(BlobIndex(0)), None);
+            send_action(&client, cli.id.clone(), contract_name, &action, vec![transfer_blob, identity_blob.clone()]).await;
+        }
+        Commands::SetDiscountRules { rules } => {
+            let rules: Vec<DiscountRule> = serde_json::from_str(&rules).unwrap();
+
+            // Create the set discount rules action, only the owner may send it
+            let action = TicketAppAction::SetDiscountRules {
+                event_id: cli.event_id.clone(),
+                rules,
+            };
+
+            // Send the blob transaction
+            send_action(&client, cli.id.clone(), contract_name, &action, vec![identity_blob.clone()]).await;
+        }
+        Commands::SetEligibility { eligibility } => {
+            // Create the set eligibility action, only the owner may send it
+            let action = TicketAppAction::SetEligibility {
+                event_id: cli.event_id.clone(),
+                eligibility,
+            };
+
+            // Send the blob transaction
+            send_action(&client, cli.id.clone(), contract_name, &action, vec![identity_blob.clone()]).await;
+        }
+        Commands::SpendTicket { ticket_id } => {
+            // Create the spend ticket action
+            let action = TicketAppAction::SpendTicket {
+                event_id: cli.event_id.clone(),
+                ticket_id,
+            };
 
             // Send the blob transaction
-            let blob_tx_hash = client.send_tx_blob(&blob_tx).await.unwrap();
-            println!("✅ Blob tx sent. Tx hash: {}", blob_tx_hash);
+            send_action(&client, cli.id.clone(), contract_name, &action, vec![identity_blob.clone()]).await;
         }
     }
 }
+
+/// Prices of the tickets, in the main token and in every extra one.
+fn prices(cli: &Cli) -> PriceList {
+    std::iter::once((cli.token_contract_name.clone().into(), cli.ticket_price))
+        .chain(cli.extra_price.clone())
+        .collect()
+}
+
+/// Sends a transaction of `identity` made of the ticket app `action`, at index 0, followed by `blobs`.
+async fn send_action(client: &NodeApiHttpClient, identity: String, contract_name: &str, action: &TicketAppAction, blobs: Vec<sdk::Blob>) {
+    let blob_tx = BlobTransaction::new(identity, [vec![action.as_blob(contract_name.into())], blobs].concat());
+    let blob_tx_hash = client.send_tx_blob(&blob_tx).await.unwrap();
+    println!("✅ Blob tx sent. Tx hash: {}", blob_tx_hash);
+}
+
+/// Reads a passport proof saved as JSON.
+fn read_passport(path: &str) -> PassportData {
+    let passport = std::fs::read_to_string(path).unwrap();
+    serde_json::from_str(&passport).unwrap()
+}
+
+/// Sends a transaction like `send_action`, through the app server, which hands `proof` to the prover as private input.
+async fn send_with_proof(server: &str, identity: String, contract_name: &str, action: &TicketAppAction, blobs: Vec<sdk::Blob>, proof: &Groth16Proof) {
+    let blob_tx = BlobTransaction::new(identity, [vec![action.as_blob(contract_name.into())], blobs].concat());
+    let res = reqwest::Client::new()
+        .post(format!("{}/api/send", server))
+        .json(&serde_json::json!({ "tx": blob_tx, "proof": proof }))
+        .send()
+        .await
+        .unwrap();
+    if !res.status().is_success() {
+        eprintln!("❌ Blob tx rejected: {}", res.text().await.unwrap());
+        std::process::exit(1);
+    }
+    let blob_tx_hash: String = res.json().await.unwrap();
+    println!("✅ Blob tx sent. Tx hash: {}", blob_tx_hash);
+}
@@ -10,7 +10,7 @@ pub mod metadata {
 
 impl TxExecutorHandler for TicketApp {
     fn build_commitment_metadata(&self, _blob: &Blob) -> Result<Vec<u8>, String> {
-        borsh::to_vec(self).map_err(|e| e.to_string())
+        self.as_bytes().map_err(|e| e.to_string())
     }
 
     fn handle(&mut self, calldata: &Calldata) -> Result<sdk::HyleOutput, String> {
@@ -0,0 +1,212 @@
+use std::fmt;
+use std::str::FromStr;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{
+    ALIASES, ASEAN_COUNTRIES, COUNTRIES, EEA_COUNTRIES, EU_COUNTRIES, MERCOSUR_COUNTRIES,
+    SCHENGEN_COUNTRIES,
+};
+
+/// A country, identified by its ISO 3166 alpha-3 code.
+///
+/// Only known codes can be turned into a `Country`, so comparing countries never
+/// depends on how their name was spelled.
+#[derive(
+    BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(try_from = "String", into = "String")]
+pub struct Country([u8; 3]);
+
+impl Country {
+    /// Country of an alpha-3 code, or of one of its aliases.
+    pub fn from_code(code: &str) -> Option<Country> {
+        let code = lookup(&ALIASES, code).unwrap_or(code);
+        lookup(&COUNTRIES, code)?;
+        Some(Country(code.as_bytes().try_into().ok()?))
+    }
+
+    /// Country of an English name, or of one of its aliases. Case is ignored.
+    pub fn from_name(name: &str) -> Option<Country> {
+        COUNTRIES
+            .iter()
+            .copied()
+            .chain(ALIASES.iter().map(|&(alias, code)| (code, alias)))
+            .find(|(_, known)| known.eq_ignore_ascii_case(name))
+            .and_then(|(code, _)| Country::from_code(code))
+    }
+
+    pub fn code(&self) -> &str {
+        std::str::from_utf8(&self.0).unwrap_or_default()
+    }
+
+    pub fn name(&self) -> &'static str {
+        lookup(&COUNTRIES, self.code()).unwrap_or_default()
+    }
+
+    pub fn is_in(&self, region: &Region) -> bool {
+        region.contains(*self)
+    }
+
+    /// Regions the country is a member of.
+    pub fn regions(&self) -> impl Iterator<Item = Region> + '_ {
+        Region::ALL
+            .into_iter()
+            .filter(move |region| region.contains(*self))
+    }
+}
+
+impl FromStr for Country {
+    type Err = String;
+
+    /// Parses an alpha-3 code, a name, or one of their aliases.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Country::from_code(s)
+            .or_else(|| Country::from_name(s))
+            .ok_or(format!("Unknown country {}", s))
+    }
+}
+
+/// Decodes through `Country::from_code` like serde does, so that a state or an action cannot
+/// carry a code the tables do not know, nor an alias in place of its alpha-3 code.
+impl BorshDeserialize for Country {
+    fn deserialize_reader<R: borsh::io::Read>(reader: &mut R) -> borsh::io::Result<Self> {
+        let code = <[u8; 3]>::deserialize_reader(reader)?;
+        std::str::from_utf8(&code)
+            .ok()
+            .and_then(Country::from_code)
+            .filter(|country| country.0 == code)
+            .ok_or_else(|| {
+                borsh::io::Error::new(
+                    borsh::io::ErrorKind::InvalidData,
+                    format!("Unknown country code {:?}", String::from_utf8_lossy(&code)),
+                )
+            })
+    }
+}
+
+impl TryFrom<String> for Country {
+    type Error = String;
+
+    fn try_from(code: String) -> Result<Self, Self::Error> {
+        Country::from_code(&code).ok_or(format!("Unknown country code {}", code))
+    }
+}
+
+impl From<Country> for String {
+    fn from(country: Country) -> Self {
+        country.code().to_string()
+    }
+}
+
+impl fmt::Debug for Country {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl fmt::Display for Country {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Group of countries discount and eligibility rules can refer to.
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq,
+)]
+pub enum Region {
+    Eu,
+    Eea,
+    Schengen,
+    Asean,
+    Mercosur,
+}
+
+impl Region {
+    pub const ALL: [Region; 5] = [
+        Region::Eu,
+        Region::Eea,
+        Region::Schengen,
+        Region::Asean,
+        Region::Mercosur,
+    ];
+
+    pub fn contains(&self, country: Country) -> bool {
+        let members: &[&str] = match self {
+            Region::Eu => &EU_COUNTRIES,
+            Region::Eea => &EEA_COUNTRIES,
+            Region::Schengen => &SCHENGEN_COUNTRIES,
+            Region::Asean => &ASEAN_COUNTRIES,
+            Region::Mercosur => &MERCOSUR_COUNTRIES,
+        };
+        members.binary_search(&country.code()).is_ok()
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Region::ALL
+            .into_iter()
+            .find(|region| region.to_string().eq_ignore_ascii_case(s))
+            .ok_or(format!(
+                "Unknown region {}, expected one of {:?}",
+                s,
+                Region::ALL
+            ))
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Region::Eu => "EU",
+            Region::Eea => "EEA",
+            Region::Schengen => "Schengen",
+            Region::Asean => "ASEAN",
+            Region::Mercosur => "Mercosur",
+        })
+    }
+}
+
+/// Value of `key` in a table sorted by key.
+fn lookup(table: &[(&str, &'static str)], key: &str) -> Option<&'static str> {
+    table
+        .binary_search_by_key(&key, |(known, _)| known)
+        .ok()
+        .map(|index| table[index].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn knows_icao_nationalities() {
+        for code in [
+            "GBD", "GBN", "GBO", "GBP", "GBS", "UNA", "UNO", "XXA", "XXB", "XXC",
+        ] {
+            assert_eq!(
+                Country::from_code(code).map(|c| c.to_string()),
+                Some(code.to_string())
+            );
+        }
+        assert_eq!(Country::from_code("RKS"), Country::from_code("XKX"));
+        assert!(Country::from_code("TLS").unwrap().is_in(&Region::Asean));
+    }
+
+    #[test]
+    fn decodes_only_known_codes_from_borsh() {
+        let country = Country::from_code("FRA").unwrap();
+        let bytes = borsh::to_vec(&country).unwrap();
+        assert_eq!(borsh::from_slice::<Country>(&bytes).unwrap(), country);
+
+        assert!(borsh::from_slice::<Country>(b"ZZZ").is_err());
+        assert!(borsh::from_slice::<Country>(&[0xff; 3]).is_err());
+        // Aliases are only accepted in text, not in place of the alpha-3 code.
+        assert!(borsh::from_slice::<Country>(b"RKS").is_err());
+    }
+}
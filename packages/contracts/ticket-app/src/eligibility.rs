@@ -0,0 +1,477 @@
+use std::fmt;
+use std::ops::{BitAnd, BitOr, Not};
+use std::str::FromStr;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+use crate::countries::{Country, Region};
+
+/// Deepest nesting accepted in rules, to bound the cycles spent evaluating them.
+pub const MAX_DEPTH: usize = 16;
+
+/// Expression over nationalities, e.g. `Region(EEA) & !Country(CHE) | Country(TWN)`.
+///
+/// `!` binds tighter than `&`, which binds tighter than `|`. Expressions can be built with
+/// these operators in Rust, or parsed from the same syntax. Chains of `&` and `|` are kept as
+/// flat lists, so only nesting counts towards `MAX_DEPTH`, not the length of a chain.
+#[derive(BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Eligibility {
+    Country(Country),
+    Region(Region),
+    Not(Box<Eligibility>),
+    /// Every operand matches.
+    All(Vec<Eligibility>),
+    /// At least one operand matches.
+    Any(Vec<Eligibility>),
+}
+
+impl Eligibility {
+    pub fn contains(&self, nationality: Country) -> bool {
+        match self {
+            Eligibility::Country(country) => *country == nationality,
+            Eligibility::Region(region) => region.contains(nationality),
+            Eligibility::Not(inner) => !inner.contains(nationality),
+            Eligibility::All(operands) => operands.iter().all(|e| e.contains(nationality)),
+            Eligibility::Any(operands) => operands.iter().any(|e| e.contains(nationality)),
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        match self {
+            Eligibility::Country(_) | Eligibility::Region(_) => 1,
+            Eligibility::Not(inner) => 1 + inner.depth(),
+            Eligibility::All(operands) | Eligibility::Any(operands) => {
+                1 + operands.iter().map(Eligibility::depth).max().unwrap_or(0)
+            }
+        }
+    }
+
+    /// Checks an expression before it is stored.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.depth() > MAX_DEPTH {
+            return Err(format!(
+                "Eligibility rule {} is nested deeper than {} levels",
+                self, MAX_DEPTH
+            ));
+        }
+        if self.has_short_list() {
+            return Err(format!(
+                "Eligibility rule {:?} combines fewer than two operands",
+                self
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn has_short_list(&self) -> bool {
+        match self {
+            Eligibility::Country(_) | Eligibility::Region(_) => false,
+            Eligibility::Not(inner) => inner.has_short_list(),
+            Eligibility::All(operands) | Eligibility::Any(operands) => {
+                operands.len() < 2 || operands.iter().any(Eligibility::has_short_list)
+            }
+        }
+    }
+
+    /// Decodes an expression nested at `depth`, failing past `MAX_DEPTH` before the
+    /// recursion can overflow the stack.
+    fn deserialize_nested<R: borsh::io::Read>(
+        reader: &mut R,
+        depth: usize,
+    ) -> borsh::io::Result<Self> {
+        if depth > MAX_DEPTH {
+            return Err(borsh::io::Error::new(
+                borsh::io::ErrorKind::InvalidData,
+                format!(
+                    "Eligibility rule is nested deeper than {} levels",
+                    MAX_DEPTH
+                ),
+            ));
+        }
+
+        let operands = |reader: &mut R| -> borsh::io::Result<Vec<Eligibility>> {
+            let len = u32::deserialize_reader(reader)?;
+            // Operands are pushed one by one, a forged length does not allocate upfront.
+            let mut operands = Vec::new();
+            for _ in 0..len {
+                operands.push(Self::deserialize_nested(reader, depth + 1)?);
+            }
+            Ok(operands)
+        };
+
+        match u8::deserialize_reader(reader)? {
+            0 => Ok(Eligibility::Country(Country::deserialize_reader(reader)?)),
+            1 => Ok(Eligibility::Region(Region::deserialize_reader(reader)?)),
+            2 => Ok(Eligibility::Not(Box::new(Self::deserialize_nested(
+                reader,
+                depth + 1,
+            )?))),
+            3 => Ok(Eligibility::All(operands(reader)?)),
+            4 => Ok(Eligibility::Any(operands(reader)?)),
+            tag => Err(borsh::io::Error::new(
+                borsh::io::ErrorKind::InvalidData,
+                format!("Unknown eligibility rule tag {}", tag),
+            )),
+        }
+    }
+}
+
+/// Written by hand rather than derived, the derived decoding recurses without bound.
+impl BorshDeserialize for Eligibility {
+    fn deserialize_reader<R: borsh::io::Read>(reader: &mut R) -> borsh::io::Result<Self> {
+        Self::deserialize_nested(reader, 1)
+    }
+}
+
+impl BitAnd for Eligibility {
+    type Output = Eligibility;
+
+    fn bitand(self, rhs: Eligibility) -> Eligibility {
+        let mut operands = match self {
+            Eligibility::All(operands) => operands,
+            operand => vec![operand],
+        };
+        match rhs {
+            Eligibility::All(rest) => operands.extend(rest),
+            operand => operands.push(operand),
+        }
+        Eligibility::All(operands)
+    }
+}
+
+impl BitOr for Eligibility {
+    type Output = Eligibility;
+
+    fn bitor(self, rhs: Eligibility) -> Eligibility {
+        let mut operands = match self {
+            Eligibility::Any(operands) => operands,
+            operand => vec![operand],
+        };
+        match rhs {
+            Eligibility::Any(rest) => operands.extend(rest),
+            operand => operands.push(operand),
+        }
+        Eligibility::Any(operands)
+    }
+}
+
+impl Not for Eligibility {
+    type Output = Eligibility;
+
+    fn not(self) -> Eligibility {
+        Eligibility::Not(Box::new(self))
+    }
+}
+
+impl fmt::Display for Eligibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Eligibility::Country(country) => write!(f, "Country({})", country),
+            Eligibility::Region(region) => write!(f, "Region({})", region),
+            // Operands are parenthesized when needed, so the output parses back the same.
+            Eligibility::Not(inner) => match **inner {
+                Eligibility::All(..) | Eligibility::Any(..) => write!(f, "!({})", inner),
+                _ => write!(f, "!{}", inner),
+            },
+            Eligibility::All(operands) => {
+                for (i, operand) in operands.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { " & " };
+                    match operand {
+                        Eligibility::All(..) | Eligibility::Any(..) => {
+                            write!(f, "{}({})", separator, operand)?
+                        }
+                        _ => write!(f, "{}{}", separator, operand)?,
+                    }
+                }
+                Ok(())
+            }
+            Eligibility::Any(operands) => {
+                for (i, operand) in operands.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { " | " };
+                    match operand {
+                        Eligibility::Any(..) => write!(f, "{}({})", separator, operand)?,
+                        _ => write!(f, "{}{}", separator, operand)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for Eligibility {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            input: s,
+            pos: 0,
+            nesting: 0,
+        };
+        let eligibility = parser.or()?;
+
+        parser.skip_whitespace();
+        if parser.pos != s.len() {
+            return Err(format!(
+                "Unexpected {:?} at position {} of eligibility rule {:?}",
+                &s[parser.pos..],
+                parser.pos,
+                s
+            ));
+        }
+
+        Ok(eligibility)
+    }
+}
+
+/// Recursive descent parser over the textual form of `Eligibility`.
+///
+/// Nesting is bounded while parsing, before a long run of `!` or `(` overflows the stack.
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    /// Number of `!` and `(` being parsed.
+    nesting: usize,
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Result<Eligibility, String> {
+        let mut eligibility = self.and()?;
+        while self.eat('|') {
+            eligibility = eligibility | self.and()?;
+        }
+        self.check_depth(&eligibility)?;
+        Ok(eligibility)
+    }
+
+    fn and(&mut self) -> Result<Eligibility, String> {
+        let mut eligibility = self.unary()?;
+        while self.eat('&') {
+            eligibility = eligibility & self.unary()?;
+        }
+        self.check_depth(&eligibility)?;
+        Ok(eligibility)
+    }
+
+    fn unary(&mut self) -> Result<Eligibility, String> {
+        if self.eat('!') {
+            self.enter()?;
+            let eligibility = !self.unary()?;
+            self.check_depth(&eligibility)?;
+            self.nesting -= 1;
+            return Ok(eligibility);
+        }
+
+        if self.eat('(') {
+            self.enter()?;
+            let eligibility = self.or()?;
+            self.expect(')')?;
+            self.nesting -= 1;
+            return Ok(eligibility);
+        }
+
+        self.skip_whitespace();
+        let start = self.pos;
+        let rest = &self.input[self.pos..];
+        let kind_len = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let kind = &rest[..kind_len];
+        self.pos += kind_len;
+
+        self.expect('(')?;
+        let rest = &self.input[self.pos..];
+        let argument_len = rest.find(')').ok_or(format!(
+            "Missing ')' after {}( in eligibility rule {:?}",
+            kind, self.input
+        ))?;
+        let argument = rest[..argument_len].trim();
+        self.pos += argument_len + 1;
+
+        match kind {
+            "Country" => Ok(Eligibility::Country(argument.parse()?)),
+            "Region" => Ok(Eligibility::Region(argument.parse()?)),
+            _ => Err(format!(
+                "Expected Country(..) or Region(..) at position {} of eligibility rule {:?}",
+                start, self.input
+            )),
+        }
+    }
+
+    fn enter(&mut self) -> Result<(), String> {
+        self.nesting += 1;
+        if self.nesting > MAX_DEPTH {
+            return Err(self.too_deep());
+        }
+        Ok(())
+    }
+
+    fn check_depth(&self, eligibility: &Eligibility) -> Result<(), String> {
+        if eligibility.depth() > MAX_DEPTH {
+            return Err(self.too_deep());
+        }
+        Ok(())
+    }
+
+    fn too_deep(&self) -> String {
+        format!(
+            "Eligibility rule {:?} is nested deeper than {} levels at position {}",
+            self.input, MAX_DEPTH, self.pos
+        )
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: char) -> bool {
+        self.skip_whitespace();
+        if self.input[self.pos..].starts_with(token) {
+            self.pos += token.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: char) -> Result<(), String> {
+        if !self.eat(token) {
+            return Err(format!(
+                "Expected '{}' at position {} of eligibility rule {:?}",
+                token, self.pos, self.input
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn country(code: &str) -> Eligibility {
+        Eligibility::Country(code.parse().unwrap())
+    }
+
+    fn region(region: Region) -> Eligibility {
+        Eligibility::Region(region)
+    }
+
+    fn assert_round_trip(eligibility: Eligibility) {
+        let text = eligibility.to_string();
+        assert_eq!(text.parse::<Eligibility>(), Ok(eligibility), "{}", text);
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        assert_round_trip(country("TWN"));
+        assert_round_trip(region(Region::Schengen));
+        assert_round_trip(!country("CHE"));
+        assert_round_trip(!!country("CHE"));
+        assert_round_trip(region(Region::Eea) & !country("CHE") | country("TWN"));
+        assert_round_trip(!(region(Region::Eu) | region(Region::Asean)));
+        assert_round_trip((country("FRA") | country("DEU")) & region(Region::Eu));
+    }
+
+    #[test]
+    fn round_trips_right_nested_chains() {
+        assert_round_trip(country("FRA") & (country("DEU") & country("ITA")));
+        assert_round_trip(country("FRA") & (country("DEU") | country("ITA")));
+        assert_round_trip(country("FRA") | (country("DEU") | country("ITA")));
+        assert_round_trip(country("FRA") | country("DEU") & country("ITA"));
+    }
+
+    #[test]
+    fn parses_with_precedence() {
+        assert_eq!(
+            "Region(EEA) & !Country(CHE) | Country(TWN)".parse(),
+            Ok((region(Region::Eea) & !country("CHE")) | country("TWN"))
+        );
+        assert_eq!(
+            " ( Country(FRA)|Country(DEU) )&Region( EU ) ".parse(),
+            Ok((country("FRA") | country("DEU")) & region(Region::Eu))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        for rule in [
+            "",
+            "Country(FRA) &",
+            "Country(FRA",
+            "(Country(FRA)",
+            "Country(FRA))",
+            "Nation(FRA)",
+            "Country(XXX)",
+            "Region(NATO)",
+        ] {
+            assert!(rule.parse::<Eligibility>().is_err(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn rejects_rules_nested_too_deep() {
+        let nots = "!".repeat(MAX_DEPTH - 1) + "Country(FRA)";
+        assert!(nots.parse::<Eligibility>().is_ok());
+
+        let nots = "!".repeat(MAX_DEPTH) + "Country(FRA)";
+        assert!(nots.parse::<Eligibility>().is_err());
+
+        let nots = "!".repeat(100_000) + "Country(FRA)";
+        assert!(nots.parse::<Eligibility>().is_err());
+
+        let parentheses = "(".repeat(100_000) + "Country(FRA)" + &")".repeat(100_000);
+        assert!(parentheses.parse::<Eligibility>().is_err());
+    }
+
+    #[test]
+    fn parses_long_chains_flat() {
+        let chain = vec!["Country(FRA)"; 1_000].join(" | ");
+        let eligibility = chain.parse::<Eligibility>().unwrap();
+        assert_eq!(eligibility, Eligibility::Any(vec![country("FRA"); 1_000]));
+        assert_eq!(eligibility.validate(), Ok(()));
+
+        let chain = vec!["Country(FRA) & Country(DEU)"; 1_000].join(" | ");
+        assert_eq!(chain.parse::<Eligibility>().unwrap().depth(), 3);
+    }
+
+    #[test]
+    fn round_trips_through_borsh() {
+        let eligibility = region(Region::Eea) & !country("CHE") | country("TWN");
+        let bytes = borsh::to_vec(&eligibility).unwrap();
+        assert_eq!(
+            borsh::from_slice::<Eligibility>(&bytes).unwrap(),
+            eligibility
+        );
+    }
+
+    #[test]
+    fn rejects_borsh_nested_too_deep() {
+        let nested = |nots: usize| {
+            let mut bytes = vec![2; nots];
+            bytes.extend(borsh::to_vec(&country("FRA")).unwrap());
+            bytes
+        };
+
+        assert!(borsh::from_slice::<Eligibility>(&nested(MAX_DEPTH - 1)).is_ok());
+        assert!(borsh::from_slice::<Eligibility>(&nested(MAX_DEPTH)).is_err());
+        assert!(borsh::from_slice::<Eligibility>(&nested(1_000_000)).is_err());
+    }
+
+    #[test]
+    fn rejects_short_lists() {
+        assert!(Eligibility::Any(vec![]).validate().is_err());
+        assert!(Eligibility::All(vec![country("FRA")]).validate().is_err());
+        assert!(Eligibility::Any(vec![country("FRA")]).validate().is_err());
+        assert!(Eligibility::Any(vec![!Eligibility::All(vec![])])
+            .validate()
+            .is_err());
+        assert_eq!(
+            Eligibility::Any(vec![country("FRA"), country("DEU")]).validate(),
+            Ok(())
+        );
+    }
+}
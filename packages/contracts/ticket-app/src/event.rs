@@ -0,0 +1,374 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+use hyle_hyllar::HyllarAction;
+use sdk::{caller::ExecutionContext, ContractName, TxContext};
+
+use crate::eligibility::Eligibility;
+use crate::payment::Proceeds;
+use crate::policy::{default_discount_rules, DiscountRule};
+use crate::public_signals::{missing_nationality, PassportSignals};
+
+pub type EventId = String;
+
+/// Price of a ticket in each token it can be paid with.
+pub type PriceList = BTreeMap<ContractName, u128>;
+
+/// Parses a `token=amount` entry of a price list.
+pub fn parse_price(price: &str) -> Result<(ContractName, u128), String> {
+    let (token, amount) = price
+        .split_once('=')
+        .ok_or(format!("Price {} should be written token=amount", price))?;
+    let amount = amount
+        .parse()
+        .map_err(|e| format!("Invalid amount in price {}: {}", price, e))?;
+
+    Ok((token.into(), amount))
+}
+
+/// Tier recorded on tickets sold at the base price.
+pub const STANDARD_TIER: &str = "standard";
+
+/// A point in time, checked against the context of the transaction.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SaleBound {
+    BlockHeight(u64),
+    /// Milliseconds since the Unix epoch.
+    Timestamp(u128),
+}
+
+impl SaleBound {
+    pub fn reached(&self, tx_ctx: &TxContext) -> bool {
+        match self {
+            SaleBound::BlockHeight(height) => tx_ctx.block_height.0 >= *height,
+            SaleBound::Timestamp(timestamp) => tx_ctx.timestamp.0 >= *timestamp,
+        }
+    }
+}
+
+/// Period during which something is on sale. Open ended when a bound is missing.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SaleWindow {
+    pub start: Option<SaleBound>,
+    pub end: Option<SaleBound>,
+}
+
+impl SaleWindow {
+    pub fn contains(&self, tx_ctx: &TxContext) -> bool {
+        self.start
+            .as_ref()
+            .map_or(true, |start| start.reached(tx_ctx))
+            && !self.end.as_ref().is_some_and(|end| end.reached(tx_ctx))
+    }
+}
+
+/// Alternative price for tickets bought within `window`, e.g. early-bird or last-minute.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone)]
+pub struct PriceTier {
+    pub name: String,
+    pub window: SaleWindow,
+    pub prices: PriceList,
+}
+
+/// A ticket offering, with its own price and buyer requirements.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone)]
+pub struct Event {
+    pub prices: PriceList,
+    /// Maximum number of tickets sold for the event.
+    pub capacity: u32,
+    pub sold: u32,
+    pub sale_window: SaleWindow,
+    /// Checked in order, the first tier whose window is open sets the price.
+    pub price_tiers: Vec<PriceTier>,
+    /// Minimum age buyers must prove, if the event is age restricted.
+    pub min_age: Option<u8>,
+    /// Nationalities allowed to buy tickets, if the event is restricted.
+    pub eligibility: Option<Eligibility>,
+    /// Checked in order, the first rule matching the buyer's nationality sets their discount.
+    pub discount_rules: Vec<DiscountRule>,
+    /// Nullifiers of the passports that already bought a ticket for this event.
+    pub nullifiers: BTreeSet<[u8; 32]>,
+    /// Set by the owner, stops the sales and opens refunds.
+    pub cancelled: bool,
+    /// When set, proceeds are held until this point, which should come after the event.
+    /// Fixed when the event is added, so the owner cannot release them early.
+    pub escrow_release: Option<SaleBound>,
+    /// Payments received for the event, by token.
+    pub proceeds: BTreeMap<ContractName, Proceeds>,
+}
+
+impl Event {
+    pub fn new(prices: PriceList, capacity: u32, min_age: Option<u8>) -> Self {
+        Event {
+            prices,
+            capacity,
+            sold: 0,
+            sale_window: SaleWindow::default(),
+            price_tiers: Vec::new(),
+            min_age,
+            eligibility: None,
+            discount_rules: default_discount_rules(),
+            nullifiers: BTreeSet::new(),
+            cancelled: false,
+            escrow_release: None,
+            proceeds: BTreeMap::new(),
+        }
+    }
+
+    /// Number of tickets still on sale.
+    pub fn remaining(&self) -> u32 {
+        self.capacity.saturating_sub(self.sold)
+    }
+
+    /// Name and base prices of the tier tickets are currently sold at.
+    pub fn current_tier(&self, tx_ctx: &TxContext) -> (&str, &PriceList) {
+        self.price_tiers
+            .iter()
+            .find(|tier| tier.window.contains(tx_ctx))
+            .map(|tier| (tier.name.as_str(), &tier.prices))
+            .unwrap_or((STANDARD_TIER, &self.prices))
+    }
+
+    /// Name of the current tier and its base price in `token`.
+    pub fn base_price(
+        &self,
+        tx_ctx: &TxContext,
+        token: &ContractName,
+    ) -> Result<(&str, u128), String> {
+        let (tier, prices) = self.current_tier(tx_ctx);
+        prices.get(token).map(|&price| (tier, price)).ok_or(format!(
+            "Tickets of the {} tier cannot be paid in {}, only in {:?}",
+            tier,
+            token,
+            prices.keys().collect::<Vec<_>>()
+        ))
+    }
+
+    /// Checks that the holder of `passport` may buy a ticket for this event.
+    pub fn check_buyer(
+        &self,
+        tx_ctx: &TxContext,
+        passport: &PassportSignals,
+    ) -> Result<(), String> {
+        if self.cancelled {
+            return Err("Event was cancelled".to_string());
+        }
+
+        if !self.sale_window.contains(tx_ctx) {
+            return Err(format!(
+                "Event is not on sale at block {} ({} ms); sale window: {:?}",
+                tx_ctx.block_height.0, tx_ctx.timestamp.0, self.sale_window
+            ));
+        }
+
+        if self.remaining() == 0 {
+            return Err(format!(
+                "Event is sold out, all {} tickets were sold",
+                self.capacity
+            ));
+        }
+
+        self.check_holder(Some(passport))
+    }
+
+    /// Checks that the holder of `passport` may hold a ticket for this event, whether they
+    /// buy it or are given one. Holders without a passport proof only pass unrestricted events.
+    pub fn check_holder(&self, passport: Option<&PassportSignals>) -> Result<(), String> {
+        // Identities are cheap to create, the passport nullifier is what makes a holder unique.
+        if passport.is_some_and(|passport| self.nullifiers.contains(&passport.nullifier)) {
+            return Err("A ticket was already bought or received with this passport".to_string());
+        }
+
+        self.check_eligibility(passport)?;
+
+        if let Some(min_age) = self.min_age {
+            match passport.and_then(|passport| passport.older_than) {
+                Some(older_than) if older_than >= min_age => {}
+                Some(older_than) => {
+                    return Err(format!(
+                        "Ticket requires holders aged {} or over but the passport proof only attests {}",
+                        min_age, older_than
+                    ))
+                }
+                None => {
+                    return Err(format!(
+                        "Ticket requires holders aged {} or over but no passport proof attests an age",
+                        min_age
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that tickets may be held by the holder of `passport`.
+    ///
+    /// Restricted events require a disclosed nationality, or anyone could hide theirs.
+    pub fn check_eligibility(&self, passport: Option<&PassportSignals>) -> Result<(), String> {
+        let Some(eligibility) = &self.eligibility else {
+            return Ok(());
+        };
+
+        match passport.and_then(|passport| passport.nationality) {
+            Some(nationality) if eligibility.contains(nationality) => Ok(()),
+            Some(nationality) => Err(format!(
+                "Tickets are restricted to {}, {} nationals are not eligible",
+                eligibility,
+                nationality.name()
+            )),
+            None => Err(format!(
+                "Tickets are restricted to {}, {}",
+                eligibility,
+                missing_nationality(passport)
+            )),
+        }
+    }
+
+    /// Checks that escrowed proceeds cannot be released before the sales end.
+    pub fn check_escrow(&self) -> Result<(), String> {
+        let Some(release) = &self.escrow_release else {
+            return Ok(());
+        };
+
+        match (&self.sale_window.end, release) {
+            (Some(SaleBound::BlockHeight(end)), SaleBound::BlockHeight(at)) if at >= end => Ok(()),
+            (Some(SaleBound::Timestamp(end)), SaleBound::Timestamp(at)) if at >= end => Ok(()),
+            (end, _) => Err(format!(
+                "Escrow release {:?} should come after the end of the sales {:?}, in the same unit",
+                release, end
+            )),
+        }
+    }
+
+    /// Adds `amount` of `token` to the proceeds, leaving them untouched when it overflows.
+    pub fn collect(&mut self, token: &ContractName, amount: u128) -> Result<(), String> {
+        let mut proceeds = self.proceeds.get(token).cloned().unwrap_or_default();
+        proceeds.collect(amount)?;
+        self.proceeds.insert(token.clone(), proceeds);
+
+        Ok(())
+    }
+
+    /// Checks that the owner may withdraw the proceeds of this event.
+    pub fn check_withdrawal(&self, tx_ctx: &TxContext) -> Result<(), String> {
+        // Proceeds of a cancelled event belong to the ticket holders, escrowed or not.
+        if self.cancelled {
+            return Err("Event was cancelled, its proceeds are kept for refunds".to_string());
+        }
+
+        let Some(release) = &self.escrow_release else {
+            return Ok(());
+        };
+
+        if !release.reached(tx_ctx) {
+            return Err(format!(
+                "Proceeds are held in escrow until {:?}, now at block {} ({} ms)",
+                release, tx_ctx.block_height.0, tx_ctx.timestamp.0
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that `erc20_action` pays at least `amount` of `token` to the contract, either
+    /// from the caller's balance or from an allowance.
+    pub fn check_payment(
+        &self,
+        ctx: &ExecutionContext,
+        erc20_action: HyllarAction,
+        erc20_name: &ContractName,
+        token: &ContractName,
+        amount: u128,
+    ) -> Result<(), String> {
+        let (recipient, transferred) = match erc20_action {
+            HyllarAction::Transfer { recipient, amount } => (recipient, amount),
+            // Spends an allowance, e.g. when a sponsor or a wallet contract pays for the buyer.
+            HyllarAction::TransferFrom {
+                recipient, amount, ..
+            } => (recipient, amount),
+            other => {
+                return Err(format!(
+                    "Payment should be a Transfer or a TransferFrom but was {:?}",
+                    other
+                ))
+            }
+        };
+
+        if recipient != ctx.contract_name.0 {
+            return Err(format!(
+                "Transfer recipient should be {} but was {}",
+                ctx.contract_name, &recipient
+            ));
+        }
+
+        if token != erc20_name {
+            return Err(format!(
+                "Transfer token should be {} but was {}",
+                token, erc20_name
+            ));
+        }
+
+        if transferred < amount {
+            return Err(format!(
+                "Transfer amount should be at least {} but was {}",
+                amount, transferred
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(end: Option<SaleBound>, release: Option<SaleBound>) -> Event {
+        let mut event = Event::new(BTreeMap::new(), 10, None);
+        event.sale_window.end = end;
+        event.escrow_release = release;
+        event
+    }
+
+    #[test]
+    fn releases_escrow_after_the_sales() {
+        assert!(event(None, None).check_escrow().is_ok());
+        assert!(event(Some(SaleBound::BlockHeight(10)), None)
+            .check_escrow()
+            .is_ok());
+        assert!(event(
+            Some(SaleBound::BlockHeight(10)),
+            Some(SaleBound::BlockHeight(10))
+        )
+        .check_escrow()
+        .is_ok());
+        assert!(event(
+            Some(SaleBound::Timestamp(1_000)),
+            Some(SaleBound::Timestamp(2_000))
+        )
+        .check_escrow()
+        .is_ok());
+    }
+
+    #[test]
+    fn rejects_escrow_released_during_the_sales() {
+        assert!(event(None, Some(SaleBound::BlockHeight(10)))
+            .check_escrow()
+            .is_err());
+        assert!(event(
+            Some(SaleBound::BlockHeight(10)),
+            Some(SaleBound::BlockHeight(9))
+        )
+        .check_escrow()
+        .is_err());
+        assert!(event(
+            Some(SaleBound::Timestamp(10)),
+            Some(SaleBound::BlockHeight(10))
+        )
+        .check_escrow()
+        .is_err());
+    }
+}
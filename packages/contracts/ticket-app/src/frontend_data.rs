@@ -11,9 +11,6 @@ pub struct Groth16Proof {
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone)]
 pub struct PassportData {
-    pub nationality: String,
-    pub name: String,
-    pub older_than: String,
     pub proof: Groth16Proof,
     pub public_signal: Vec<String>,
 }
@@ -34,8 +31,8 @@ impl std::fmt::Display for PassportData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{{ nationality: {:?}\n, name: {:?}\n, older_than: {:?}\n, proof: {:?}\n, public_signal: {:?}\n }}",
-            self.nationality, self.name, self.older_than, self.proof, self.public_signal
+            "{{ proof: {:?}\n, public_signal: {:?}\n }}",
+            self.proof, self.public_signal
         )
     }
 }
@@ -55,9 +52,6 @@ pub fn create_mock_fe_data() -> FrontendData {
     FrontendData {
         data_type: "pod-pcd".to_string(),
         passport: PassportData {
-            nationality: "TWN".to_string(),
-            name: "[\"BO BO\",\"CHANG\"]".to_string(),
-            older_than: "14".to_string(),
             proof: Groth16Proof {
                 a: vec![
                     "2218721293884717613638497048031931256188091742657509215368815339884510653958".to_string(),
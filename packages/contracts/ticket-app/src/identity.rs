@@ -0,0 +1,30 @@
+use std::collections::BTreeSet;
+
+use hyle_hydentity::HydentityAction;
+use sdk::{caller::ExecutionContext, ContractName};
+
+/// Checks that the transaction verifies the caller's identity with one of `identity_contracts`.
+///
+/// Identity contracts take raw borsh actions, and check the proof of the identity themselves.
+pub fn check_identity(
+    ctx: &ExecutionContext,
+    calldata: &sdk::Calldata,
+    identity_contracts: &BTreeSet<ContractName>,
+) -> Result<(), String> {
+    let verified = calldata.blobs.iter().any(|(_, blob)| {
+        identity_contracts.contains(&blob.contract_name)
+            && matches!(
+                borsh::from_slice::<HydentityAction>(&blob.data.0),
+                Ok(HydentityAction::VerifyIdentity { ref account, .. }) if account == &ctx.caller.0
+            )
+    });
+
+    if !verified {
+        return Err(format!(
+            "No identity verification for {:?} found in the transaction, expected one from {:?}",
+            ctx.caller, identity_contracts
+        ));
+    }
+
+    Ok(())
+}
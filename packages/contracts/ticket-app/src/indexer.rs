@@ -1,18 +1,14 @@
-use std::str;
-
 use anyhow::{anyhow, Context, Result};
-use client_sdk::{
-    contract_indexer::{
-        axum::{extract::State, http::StatusCode, response::IntoResponse, Json, Router},
-        utoipa::openapi::OpenApi,
-        utoipa_axum::{router::OpenApiRouter, routes},
-        AppError, ContractHandler, ContractHandlerStore,
-    },
-    transaction_builder::TxExecutorHandler,
+use client_sdk::contract_indexer::{
+    axum::{extract::State, http::StatusCode, response::IntoResponse, Json, Router},
+    utoipa::openapi::OpenApi,
+    utoipa_axum::{router::OpenApiRouter, routes},
+    AppError, ContractHandler, ContractHandlerStore,
 };
 use sdk::Hashed;
 use serde::Serialize;
 
+use crate::event::EventId;
 use crate::*;
 use client_sdk::contract_indexer::axum;
 use client_sdk::contract_indexer::utoipa;
@@ -47,14 +43,29 @@ impl ContractHandler for TicketApp {
             private_input: vec![],
         };
 
-        let hyle_output = self.handle(&calldata).map_err(|e| anyhow::anyhow!(e))?;
-        let program_outputs = str::from_utf8(&hyle_output.program_outputs).unwrap_or("no output");
+        let (action, ctx) = sdk::utils::parse_raw_calldata::<TicketAppAction>(&calldata)
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        // Only settled transactions reach the indexer, so their passport proof has
+        // already been verified on-chain. Its private input is not available here.
+        let program_outputs = self
+            .apply(action, &ctx, &calldata)
+            .map_err(|e| anyhow::anyhow!(e))?;
 
         sdk::info!("🚀 Executed {contract_name}: {}", program_outputs);
         Ok(())
     }
 }
 
+/// Contract state, along with the tickets still on sale so frontends can stop
+/// selling events that are sold out.
+#[derive(Serialize)]
+pub struct StateResponse {
+    #[serde(flatten)]
+    pub state: TicketApp,
+    pub remaining_tickets: BTreeMap<EventId, u32>,
+}
+
 #[utoipa::path(
     get,
     path = "/state",
@@ -63,12 +74,21 @@ impl ContractHandler for TicketApp {
         (status = OK, description = "Get json state of contract")
     )
 )]
-pub async fn get_state<S: Serialize + Clone + 'static>(
-    State(state): State<ContractHandlerStore<S>>,
+pub async fn get_state(
+    State(state): State<ContractHandlerStore<TicketApp>>,
 ) -> Result<impl IntoResponse, AppError> {
     let store = state.read().await;
-    store.state.clone().map(Json).ok_or(AppError(
-        StatusCode::NOT_FOUND,
-        anyhow!("No state found for contract '{}'", store.contract_name),
-    ))
+    store
+        .state
+        .clone()
+        .map(|state| {
+            Json(StateResponse {
+                remaining_tickets: state.remaining_tickets(),
+                state,
+            })
+        })
+        .ok_or(AppError(
+            StatusCode::NOT_FOUND,
+            anyhow!("No state found for contract '{}'", store.contract_name),
+        ))
 }
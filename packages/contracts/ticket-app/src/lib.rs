@@ -1,12 +1,36 @@
+use std::collections::{BTreeMap, BTreeSet};
+
 use borsh::{io::Error, BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 
 use hyle_hyllar::HyllarAction;
-use sdk::{caller::ExecutionContext, BlobIndex, ContractName, Identity, RunResult};
+use sdk::{caller::ExecutionContext, BlockHeight, ContractName, Identity, RunResult, TxContext};
 
-use crate::constants::EU_COUNTRIES;
+use crate::countries::Country;
+use crate::eligibility::Eligibility;
+use crate::event::{Event, EventId, PriceList};
+use crate::frontend_data::Groth16Proof;
+use crate::identity::check_identity;
+use crate::payment::{check_payouts, find_payment, Payout, Proceeds};
+use crate::policy::{
+    check_compliance, default_denylist, discount, validate_discount_rules, DiscountRule,
+};
+use crate::pricing::{apply_rate, quote};
+use crate::public_signals::PassportSignals;
+use crate::verifier::PassportVerifyingKey;
 
 pub mod constants;
+pub mod countries;
+pub mod eligibility;
+pub mod event;
+pub mod frontend_data;
+pub mod identity;
+pub mod payment;
+pub mod policy;
+pub mod pricing;
+pub mod public_signals;
+pub mod state;
+pub mod verifier;
 
 #[cfg(feature = "client")]
 pub mod client;
@@ -19,32 +43,29 @@ impl sdk::ZkContract for TicketApp {
         // Parse contract inputs
         let (action, ctx) = sdk::utils::parse_raw_calldata::<TicketAppAction>(calldata)?;
 
-        let transfer_action =
-            sdk::utils::parse_structured_blob::<HyllarAction>(&calldata.blobs, &BlobIndex(1))
-                .ok_or("failed to parse hyllar action")?;
-
-        let transfer_action_contract_name = calldata
-            .blobs
-            .get(&BlobIndex(1))
-            .unwrap()
-            .contract_name
-            .clone();
+        // The passport proof only travels in the private input, so it is checked here
+        // rather than in `apply`, which the indexer also uses to replay settled txs.
+        match (action.requirements().passport, action.passport_signals()) {
+            (Need::Never, _) | (Need::Optional, None) => {}
+            (_, Some(public_signals)) => {
+                let proof = borsh::from_slice::<Groth16Proof>(&calldata.private_input)
+                    .map_err(|_| "failed to decode passport proof from private input")?;
+                self.verify_passport(&proof, public_signals)?;
+            }
+            (Need::Required, None) => return Err("Action requires a passport proof".to_string()),
+        }
 
         // Execute the given action
-        let res = match action {
-            TicketAppAction::BuyTicket { nationality } => self.buy_ticket(
-                &ctx,
-                &nationality,
-                transfer_action.data.parameters,
-                transfer_action_contract_name,
-            )?,
-            TicketAppAction::HasTicket => self.has_ticket(&ctx)?,
-        };
+        let res = self.apply(action, &ctx, calldata)?;
+
+        // The initial commitment is taken before executing, the next one is in the current
+        // layout. A failed action keeps the state, and so its legacy commitment.
+        self.migrated_from = None;
 
         Ok((res, ctx, vec![]))
     }
 
-    /// In this example, we serialize the full state on-chain.
+    /// In this example, we serialize the full state on-chain, in a versioned layout.
     fn commit(&self) -> sdk::StateCommitment {
         sdk::StateCommitment(self.as_bytes().expect("Failed to encode TicketApp"))
     }
@@ -52,11 +73,165 @@ impl sdk::ZkContract for TicketApp {
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone)]
 pub enum TicketAppAction {
-    BuyTicket { nationality: String },
-    HasTicket,
+    /// Buys a ticket. The matching Groth16 proof is passed as private input.
+    BuyTicket {
+        event_id: EventId,
+        public_signals: Vec<String>,
+    },
+    HasTicket {
+        event_id: EventId,
+    },
+    /// Marks one of the caller's tickets as used, e.g. when entering the venue.
+    SpendTicket {
+        event_id: EventId,
+        ticket_id: TicketId,
+    },
+    /// Gives one of the caller's tickets to `recipient`. The recipient may prove their
+    /// passport to keep the discount, otherwise the difference with their price is paid.
+    /// Their passport is checked like a buyer's, so restricted events and a non-empty
+    /// denylist require it.
+    TransferTicket {
+        ticket_id: TicketId,
+        recipient: Identity,
+        recipient_passport: Option<Vec<String>>,
+    },
+    // Admin actions, only the owner may call them.
+    /// Replaces the tokens an event's tickets can be paid with, and their base prices.
+    SetPrice {
+        event_id: EventId,
+        prices: PriceList,
+    },
+    /// Replaces the discount rules of an event.
+    SetDiscountRules {
+        event_id: EventId,
+        rules: Vec<DiscountRule>,
+    },
+    /// Restricts the nationalities allowed to buy an event's tickets, or lifts the restriction.
+    SetEligibility {
+        event_id: EventId,
+        eligibility: Option<Eligibility>,
+    },
+    AddEvent {
+        event_id: EventId,
+        event: Box<Event>,
+    },
+    /// Removes an event no ticket was sold for.
+    RemoveEvent {
+        event_id: EventId,
+    },
+    /// Replaces the contracts trusted to verify the identity of callers.
+    SetIdentityContracts {
+        identity_contracts: BTreeSet<ContractName>,
+    },
+    /// Replaces the nationalities tickets cannot be sold to.
+    SetDenylist {
+        denylist: BTreeSet<Country>,
+    },
+    /// Stops the sales of an event, and lets holders claim a refund of their tickets.
+    CancelEvent {
+        event_id: EventId,
+    },
+    /// Refunds one of the caller's tickets for a cancelled event. Comes with a hyllar
+    /// transfer of the amount paid for it, whose caller is this blob.
+    ClaimRefund {
+        ticket_id: TicketId,
+    },
+    /// Pays `amount` of an event's proceeds in `token` to `recipient`. Comes with a hyllar
+    /// transfer of that amount, whose caller is this blob.
+    Withdraw {
+        event_id: EventId,
+        token: ContractName,
+        amount: u128,
+        recipient: Identity,
+    },
+}
+
+/// Whether an action needs a companion blob in its transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Need {
+    Never,
+    Optional,
+    Required,
+}
+
+/// Companion blobs an action needs in its transaction, checked before it is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlobRequirements {
+    /// Hyllar action paying the contract.
+    pub payment: Need,
+    /// Passport proof, whose public signals are in the action and the proof in the private input.
+    pub passport: Need,
+    /// Verification of the caller's identity by an allowlisted identity contract.
+    pub identity: Need,
+    /// Hyllar transfer out of the contract, whose caller is the action's blob.
+    pub payout: Need,
 }
 
 impl TicketAppAction {
+    pub fn requirements(&self) -> BlobRequirements {
+        match self {
+            TicketAppAction::BuyTicket { .. } => BlobRequirements {
+                payment: Need::Required,
+                passport: Need::Required,
+                identity: Need::Required,
+                payout: Need::Never,
+            },
+            // The recipient only pays when they are not eligible for the buyer's discount.
+            TicketAppAction::TransferTicket { .. } => BlobRequirements {
+                payment: Need::Optional,
+                passport: Need::Optional,
+                identity: Need::Required,
+                payout: Need::Never,
+            },
+            // Only reads the state.
+            TicketAppAction::HasTicket { .. } => BlobRequirements {
+                payment: Need::Never,
+                passport: Need::Never,
+                identity: Need::Never,
+                payout: Need::Never,
+            },
+            TicketAppAction::Withdraw { .. } => BlobRequirements {
+                payment: Need::Never,
+                passport: Need::Never,
+                identity: Need::Required,
+                payout: Need::Required,
+            },
+            // Tickets bought for free are refunded without a transfer.
+            TicketAppAction::ClaimRefund { .. } => BlobRequirements {
+                payment: Need::Never,
+                passport: Need::Never,
+                identity: Need::Required,
+                payout: Need::Optional,
+            },
+            // Act on the caller's ticket or, for the owner, on the configuration.
+            TicketAppAction::SpendTicket { .. }
+            | TicketAppAction::SetPrice { .. }
+            | TicketAppAction::SetDiscountRules { .. }
+            | TicketAppAction::SetEligibility { .. }
+            | TicketAppAction::AddEvent { .. }
+            | TicketAppAction::RemoveEvent { .. }
+            | TicketAppAction::SetIdentityContracts { .. }
+            | TicketAppAction::SetDenylist { .. }
+            | TicketAppAction::CancelEvent { .. } => BlobRequirements {
+                payment: Need::Never,
+                passport: Need::Never,
+                identity: Need::Required,
+                payout: Need::Never,
+            },
+        }
+    }
+
+    /// Public signals of the passport proof the action comes with, if any.
+    pub fn passport_signals(&self) -> Option<&[String]> {
+        match self {
+            TicketAppAction::BuyTicket { public_signals, .. } => Some(public_signals),
+            TicketAppAction::TransferTicket {
+                recipient_passport, ..
+            } => recipient_passport.as_deref(),
+            _ => None,
+        }
+    }
+
     pub fn as_blob(&self, contract_name: sdk::ContractName) -> sdk::Blob {
         sdk::Blob {
             contract_name,
@@ -65,106 +240,1022 @@ impl TicketAppAction {
     }
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone)]
+pub type TicketId = u64;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Ticket {
+    pub id: TicketId,
+    pub owner: Identity,
+    pub event: EventId,
+    pub tier: String,
+    pub used: bool,
+    pub purchased_at: BlockHeight,
+    /// Amount paid for the ticket, discount included.
+    pub paid: u128,
+    /// Price of the ticket's tier when it was bought, before discount.
+    pub base_price: u128,
+    /// Share of `base_price` paid so far, in basis points.
+    pub rate: u128,
+    /// Token the ticket was paid in, and refunded in.
+    pub token: ContractName,
+    pub refunded: bool,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default)]
 pub struct TicketApp {
-    pub ticket_price: (ContractName, u128),
-    pub tickets: Vec<Identity>,
+    /// Identity allowed to administrate the contract.
+    pub owner: Identity,
+    pub events: BTreeMap<EventId, Event>,
+    /// Tickets sold so far, across all events, indexed by their id.
+    pub tickets: Vec<Ticket>,
+    /// Key of the passport disclosure circuit, pinned when the contract is registered.
+    pub passport_vk: Option<PassportVerifyingKey>,
+    /// Scope passport proofs must be generated for, pinned with the key. Nullifiers are only
+    /// unique within a scope, so proofs for other scopes could buy again.
+    pub passport_scope: Option<[u8; 32]>,
+    /// Contracts trusted to verify the identity of callers, e.g. hydentity.
+    pub identity_contracts: BTreeSet<ContractName>,
+    /// Alpha-3 codes of the nationalities tickets cannot be sold to.
+    pub denylist: BTreeSet<Country>,
+    /// Commitment of the older layout the state was migrated from, see `state`. Only kept
+    /// in memory, it is never part of a commitment.
+    #[borsh(skip)]
+    #[serde(skip)]
+    pub migrated_from: Option<Vec<u8>>,
+    /// Why the commitment this state was built from could not be decoded, see
+    /// `From<StateCommitment>`. Every action then fails with it.
+    #[borsh(skip)]
+    #[serde(skip)]
+    pub decode_error: Option<String>,
 }
 
-impl Default for TicketApp {
-    fn default() -> Self {
+/// Some helper methods for the state
+impl TicketApp {
+    pub fn new(
+        owner: Identity,
+        events: BTreeMap<EventId, Event>,
+        passport_vk: PassportVerifyingKey,
+        passport_scope: [u8; 32],
+        identity_contracts: BTreeSet<ContractName>,
+    ) -> Self {
         TicketApp {
-            // assume ContractName::from takes a &str, or replace with your constructor
-            ticket_price: (ContractName::from("hyllar"), 10),
+            owner,
+            events,
             tickets: Vec::new(),
+            passport_vk: Some(passport_vk),
+            passport_scope: Some(passport_scope),
+            identity_contracts,
+            denylist: default_denylist(),
+            migrated_from: None,
+            decode_error: None,
         }
     }
-}
 
-/// Some helper methods for the state
-impl TicketApp {
-    pub fn new(tickets: Vec<Identity>, ticket_price: (ContractName, u128)) -> Self {
-        TicketApp {
-            tickets,
-            ticket_price,
+    /// Applies an action whose passport proof, if any, has already been verified.
+    pub fn apply(
+        &mut self,
+        action: TicketAppAction,
+        ctx: &ExecutionContext,
+        calldata: &sdk::Calldata,
+    ) -> Result<String, String> {
+        if let Some(error) = &self.decode_error {
+            return Err(error.clone());
+        }
+
+        // Anyone can name any identity in a transaction, the identity contract proves it.
+        if action.requirements().identity == Need::Required {
+            check_identity(ctx, calldata, &self.identity_contracts)?;
+        }
+
+        // Actions paying out check their transfers, no other may spend the contract's balance.
+        if action.requirements().payout == Need::Never {
+            check_payouts(ctx, calldata, &[])?;
+        }
+
+        // Every paid action of the transaction would find the same payment.
+        if action.requirements().payment != Need::Never {
+            Self::check_single_paid_action(ctx, calldata)?;
+        }
+
+        let tokens = self.payment_tokens(&action, calldata);
+        let payment = match action.requirements().payment {
+            Need::Never => None,
+            Need::Optional => find_payment(calldata, &tokens).ok(),
+            Need::Required => Some(find_payment(calldata, &tokens)?),
+        };
+
+        match action {
+            TicketAppAction::BuyTicket {
+                event_id,
+                public_signals,
+            } => {
+                let passport = PassportSignals::decode(&public_signals)?;
+                passport.check_user(&ctx.caller)?;
+                let tx_ctx = calldata
+                    .tx_ctx
+                    .as_ref()
+                    .ok_or("Missing transaction context")?;
+                let (erc20_name, erc20_action) = payment.ok_or("BuyTicket requires a payment")?;
+                self.buy_ticket(ctx, event_id, tx_ctx, &passport, erc20_action, erc20_name)
+            }
+            TicketAppAction::HasTicket { event_id } => self.has_ticket(ctx, &event_id),
+            TicketAppAction::SpendTicket {
+                event_id,
+                ticket_id,
+            } => self.spend_ticket(ctx, &event_id, ticket_id),
+            TicketAppAction::TransferTicket {
+                ticket_id,
+                recipient,
+                recipient_passport,
+            } => {
+                let recipient_passport = recipient_passport
+                    .map(|public_signals| PassportSignals::decode(&public_signals))
+                    .transpose()?;
+                if let Some(passport) = &recipient_passport {
+                    passport.check_user(&recipient)?;
+                }
+                self.transfer_ticket(
+                    ctx,
+                    ticket_id,
+                    recipient,
+                    recipient_passport.as_ref(),
+                    payment,
+                )
+            }
+            TicketAppAction::SetPrice { event_id, prices } => {
+                self.set_price(ctx, &event_id, prices)
+            }
+            TicketAppAction::SetDiscountRules { event_id, rules } => {
+                self.set_discount_rules(ctx, &event_id, rules)
+            }
+            TicketAppAction::SetEligibility {
+                event_id,
+                eligibility,
+            } => self.set_eligibility(ctx, &event_id, eligibility),
+            TicketAppAction::AddEvent { event_id, event } => self.add_event(ctx, event_id, *event),
+            TicketAppAction::RemoveEvent { event_id } => self.remove_event(ctx, &event_id),
+            TicketAppAction::SetIdentityContracts { identity_contracts } => {
+                self.set_identity_contracts(ctx, identity_contracts)
+            }
+            TicketAppAction::SetDenylist { denylist } => self.set_denylist(ctx, denylist),
+            TicketAppAction::CancelEvent { event_id } => self.cancel_event(ctx, &event_id),
+            TicketAppAction::ClaimRefund { ticket_id } => {
+                self.claim_refund(ctx, calldata, ticket_id)
+            }
+            TicketAppAction::Withdraw {
+                event_id,
+                token,
+                amount,
+                recipient,
+            } => self.withdraw(ctx, calldata, &event_id, token, amount, recipient),
+        }
+    }
+
+    pub fn verify_passport(
+        &self,
+        proof: &Groth16Proof,
+        public_signals: &[String],
+    ) -> Result<(), String> {
+        self.passport_vk
+            .as_ref()
+            .ok_or("No passport verification key pinned for this contract")?
+            .verify(proof, public_signals)?;
+
+        let passport = PassportSignals::decode(public_signals)?;
+        self.check_scope(&passport)
+    }
+
+    /// Checks that the passport proof was generated for the scope of this contract.
+    pub fn check_scope(&self, passport: &PassportSignals) -> Result<(), String> {
+        let expected = self
+            .passport_scope
+            .ok_or("No passport scope pinned for this contract")?;
+
+        match passport.scope {
+            Some(scope) if scope == expected => Ok(()),
+            Some(_) => Err("Passport proof was generated for another scope".to_string()),
+            None => Err("Passport proof does not expose its scope".to_string()),
         }
     }
 
     pub fn buy_ticket(
         &mut self,
         ctx: &ExecutionContext,
-        nationality: &str,
+        event_id: EventId,
+        tx_ctx: &TxContext,
+        passport: &PassportSignals,
         erc20_action: HyllarAction,
         erc20_name: ContractName,
     ) -> Result<String, String> {
         // Check that a blob exists matching the given action, pop it from the callee blobs.
 
-        let discount = if EU_COUNTRIES.contains(nationality) {
-            90
-        } else if nationality == "TWN" {
-            80
-        } else if nationality == "FRA" {
-            80
-        } else {
-            100
+        check_compliance(&self.denylist, Some(passport))?;
+
+        let event = self
+            .events
+            .get_mut(&event_id)
+            .ok_or(format!("Event {} does not exist", event_id))?;
+
+        let quote = quote(passport.nationality, event, &erc20_name, tx_ctx)?;
+
+        event.check_buyer(tx_ctx, passport)?;
+        event.check_payment(ctx, erc20_action, &erc20_name, &erc20_name, quote.price)?;
+        event.collect(&erc20_name, quote.price)?;
+
+        let ticket = Ticket {
+            id: self.tickets.len() as TicketId,
+            owner: ctx.caller.clone(),
+            event: event_id,
+            tier: quote.tier,
+            used: false,
+            purchased_at: tx_ctx.block_height,
+            paid: quote.price,
+            base_price: quote.base_price,
+            rate: quote.rate,
+            token: erc20_name,
+            refunded: false,
         };
 
-        // if self.tickets.contains(&ctx.caller) {
-        //     return Err(format!("Ticket already present for {:?}", &ctx.caller));
-        // }
-
-        match erc20_action {
-            HyllarAction::Transfer { recipient, amount } => {
-                if recipient != ctx.contract_name.0 {
-                    return Err(format!(
-                        "Transfer recipient should be {} but was {}; nationality: {}",
-                        ctx.contract_name, &recipient, nationality
-                    ));
-                }
+        let program_outputs = format!(
+            "Ticket {} for {} created for {:?}",
+            ticket.id, ticket.event, ctx.caller
+        );
 
-                if self.ticket_price.0 != erc20_name {
-                    return Err(format!(
-                        "Transfer token should be {} but was {}",
-                        self.ticket_price.0, &erc20_name
-                    ));
-                }
+        self.tickets.push(ticket);
+        event.sold += 1;
+        event.nullifiers.insert(passport.nullifier);
 
-                // so this should match
-                if amount < self.ticket_price.1 * discount {
-                    return Err(format!(
-                        "Transfer amount should be at least {} but was {}",
-                        self.ticket_price.1 * discount,
-                        amount
-                    ));
-                }
-            }
-            _ => {}
+        Ok(program_outputs)
+    }
+
+    pub fn has_ticket(&self, ctx: &ExecutionContext, event_id: &EventId) -> Result<String, String> {
+        if self.tickets.iter().any(|ticket| {
+            ticket.owner == ctx.caller && &ticket.event == event_id && !ticket.refunded
+        }) {
+            Ok(format!(
+                "Ticket for {} present for {:?}",
+                event_id, &ctx.caller
+            ))
+        } else {
+            Err(format!("No Ticket for {} for {:?}", event_id, &ctx.caller))
+        }
+    }
+
+    pub fn spend_ticket(
+        &mut self,
+        ctx: &ExecutionContext,
+        event_id: &EventId,
+        ticket_id: TicketId,
+    ) -> Result<String, String> {
+        let ticket = self
+            .tickets
+            .get_mut(ticket_id as usize)
+            .ok_or(format!("Ticket {} does not exist", ticket_id))?;
+
+        if &ticket.event != event_id {
+            return Err(format!(
+                "Ticket {} is for {} and not for {}",
+                ticket_id, ticket.event, event_id
+            ));
+        }
+
+        if ticket.owner != ctx.caller {
+            return Err(format!(
+                "Ticket {} is not owned by {:?}",
+                ticket_id, &ctx.caller
+            ));
+        }
+
+        if ticket.used {
+            return Err(format!("Ticket {} was already used", ticket_id));
+        }
+
+        if self
+            .events
+            .get(event_id)
+            .is_some_and(|event| event.cancelled)
+        {
+            return Err(format!("Event {} was cancelled", event_id));
         }
 
-        let program_outputs = format!("Ticket created for {:?}", ctx.caller);
+        ticket.used = true;
+
+        Ok(format!("Ticket {} spent by {:?}", ticket_id, &ctx.caller))
+    }
+
+    pub fn transfer_ticket(
+        &mut self,
+        ctx: &ExecutionContext,
+        ticket_id: TicketId,
+        recipient: Identity,
+        recipient_passport: Option<&PassportSignals>,
+        payment: Option<(ContractName, HyllarAction)>,
+    ) -> Result<String, String> {
+        let ticket = self
+            .tickets
+            .get_mut(ticket_id as usize)
+            .ok_or(format!("Ticket {} does not exist", ticket_id))?;
+
+        if ticket.owner != ctx.caller {
+            return Err(format!(
+                "Ticket {} is not owned by {:?}",
+                ticket_id, &ctx.caller
+            ));
+        }
+
+        if ticket.used {
+            return Err(format!("Ticket {} was already used", ticket_id));
+        }
+
+        let event = self
+            .events
+            .get_mut(&ticket.event)
+            .ok_or(format!("Event {} does not exist", ticket.event))?;
+
+        if event.cancelled {
+            return Err(format!("Event {} was cancelled", ticket.event));
+        }
+
+        // Tickets cannot be passed on to someone who could not have bought them.
+        let recipient_nationality = recipient_passport.and_then(|passport| passport.nationality);
+        check_compliance(&self.denylist, recipient_passport)?;
+        event.check_holder(recipient_passport)?;
+
+        // A recipient without a proven passport gets no discount. Only the part of the discount
+        // they are not entitled to is paid back, at the price the ticket was bought at.
+        let recipient_rate = discount(&event.discount_rules, recipient_nationality);
+        let clawback = apply_rate(
+            ticket.base_price,
+            recipient_rate.saturating_sub(ticket.rate),
+        )?;
+        // Checked before anything is written, so that an overflow leaves the state untouched.
+        let paid = ticket.paid.checked_add(clawback).ok_or(format!(
+            "Ticket {} paid amount overflows with a clawback of {}",
+            ticket_id, clawback
+        ))?;
+
+        if clawback > 0 {
+            let (erc20_name, erc20_action) = payment.ok_or(format!(
+                "Transferring ticket {} requires a payment of {} {}",
+                ticket_id, clawback, ticket.token
+            ))?;
+            event.check_payment(ctx, erc20_action, &erc20_name, &ticket.token, clawback)?;
+            event.collect(&erc20_name, clawback)?;
+        } else if payment.is_some() {
+            // It would be kept by the contract without being recorded in the proceeds.
+            return Err(format!(
+                "Transferring ticket {} requires no payment",
+                ticket_id
+            ));
+        }
+
+        ticket.owner = recipient;
+        ticket.paid = paid;
+        ticket.rate = ticket.rate.max(recipient_rate);
+        if let Some(passport) = recipient_passport {
+            event.nullifiers.insert(passport.nullifier);
+        }
+
+        Ok(format!(
+            "Ticket {} transferred from {:?} to {:?}",
+            ticket_id, &ctx.caller, &ticket.owner
+        ))
+    }
+
+    pub fn set_price(
+        &mut self,
+        ctx: &ExecutionContext,
+        event_id: &EventId,
+        prices: PriceList,
+    ) -> Result<String, String> {
+        self.check_owner(ctx)?;
 
-        self.tickets.push(ctx.caller.clone());
+        let event = self
+            .events
+            .get_mut(event_id)
+            .ok_or(format!("Event {} does not exist", event_id))?;
+
+        event.prices = prices;
+
+        Ok(format!(
+            "Ticket prices of {} set to {:?}",
+            event_id, event.prices
+        ))
+    }
+
+    pub fn set_discount_rules(
+        &mut self,
+        ctx: &ExecutionContext,
+        event_id: &EventId,
+        rules: Vec<DiscountRule>,
+    ) -> Result<String, String> {
+        self.check_owner(ctx)?;
+        validate_discount_rules(&rules)?;
+
+        let event = self
+            .events
+            .get_mut(event_id)
+            .ok_or(format!("Event {} does not exist", event_id))?;
+
+        event.discount_rules = rules;
+
+        Ok(format!(
+            "{} discount rules set for {}",
+            event.discount_rules.len(),
+            event_id
+        ))
+    }
+
+    pub fn set_eligibility(
+        &mut self,
+        ctx: &ExecutionContext,
+        event_id: &EventId,
+        eligibility: Option<Eligibility>,
+    ) -> Result<String, String> {
+        self.check_owner(ctx)?;
+        if let Some(eligibility) = &eligibility {
+            eligibility.validate()?;
+        }
+
+        let event = self
+            .events
+            .get_mut(event_id)
+            .ok_or(format!("Event {} does not exist", event_id))?;
+
+        event.eligibility = eligibility;
+
+        match &event.eligibility {
+            Some(eligibility) => Ok(format!(
+                "Tickets of {} restricted to {}",
+                event_id, eligibility
+            )),
+            None => Ok(format!("Tickets of {} open to all nationalities", event_id)),
+        }
+    }
+
+    pub fn add_event(
+        &mut self,
+        ctx: &ExecutionContext,
+        event_id: EventId,
+        event: Event,
+    ) -> Result<String, String> {
+        self.check_owner(ctx)?;
+        validate_discount_rules(&event.discount_rules)?;
+        if let Some(eligibility) = &event.eligibility {
+            eligibility.validate()?;
+        }
+        event.check_escrow()?;
+
+        if self.events.contains_key(&event_id) {
+            return Err(format!("Event {} already exists", event_id));
+        }
+
+        if event.sold != 0 || !event.nullifiers.is_empty() || !event.proceeds.is_empty() {
+            return Err(format!("Event {} should not have sold tickets", event_id));
+        }
+
+        let program_outputs = format!("Event {} added", event_id);
+        self.events.insert(event_id, event);
 
         Ok(program_outputs)
     }
 
-    pub fn has_ticket(&self, ctx: &ExecutionContext) -> Result<String, String> {
-        if self.tickets.contains(&ctx.caller) {
-            Ok(format!("Ticket present for {:?}", &ctx.caller))
-        } else {
-            Err(format!("No Ticket for {:?}", &ctx.caller))
+    pub fn remove_event(
+        &mut self,
+        ctx: &ExecutionContext,
+        event_id: &EventId,
+    ) -> Result<String, String> {
+        self.check_owner(ctx)?;
+
+        let event = self
+            .events
+            .get(event_id)
+            .ok_or(format!("Event {} does not exist", event_id))?;
+
+        // Holders would be left with tickets for an event that no longer exists.
+        if event.sold != 0 {
+            return Err(format!(
+                "Event {} cannot be removed, {} tickets were already sold",
+                event_id, event.sold
+            ));
+        }
+
+        self.events.remove(event_id);
+
+        Ok(format!("Event {} removed", event_id))
+    }
+
+    pub fn set_identity_contracts(
+        &mut self,
+        ctx: &ExecutionContext,
+        identity_contracts: BTreeSet<ContractName>,
+    ) -> Result<String, String> {
+        self.check_owner(ctx)?;
+
+        self.identity_contracts = identity_contracts;
+
+        Ok(format!(
+            "Identity contracts set to {:?}",
+            self.identity_contracts
+        ))
+    }
+
+    pub fn set_denylist(
+        &mut self,
+        ctx: &ExecutionContext,
+        denylist: BTreeSet<Country>,
+    ) -> Result<String, String> {
+        self.check_owner(ctx)?;
+
+        self.denylist = denylist;
+
+        Ok(format!("Denylist set to {:?}", self.denylist))
+    }
+
+    pub fn cancel_event(
+        &mut self,
+        ctx: &ExecutionContext,
+        event_id: &EventId,
+    ) -> Result<String, String> {
+        self.check_owner(ctx)?;
+
+        let event = self
+            .events
+            .get_mut(event_id)
+            .ok_or(format!("Event {} does not exist", event_id))?;
+
+        if event.cancelled {
+            return Err(format!("Event {} was already cancelled", event_id));
+        }
+
+        event.cancelled = true;
+
+        Ok(format!("Event {} cancelled", event_id))
+    }
+
+    pub fn claim_refund(
+        &mut self,
+        ctx: &ExecutionContext,
+        calldata: &sdk::Calldata,
+        ticket_id: TicketId,
+    ) -> Result<String, String> {
+        let ticket = self
+            .tickets
+            .get_mut(ticket_id as usize)
+            .ok_or(format!("Ticket {} does not exist", ticket_id))?;
+
+        if ticket.owner != ctx.caller {
+            return Err(format!(
+                "Ticket {} is not owned by {:?}",
+                ticket_id, &ctx.caller
+            ));
+        }
+
+        if ticket.used {
+            return Err(format!("Ticket {} was already used", ticket_id));
+        }
+
+        if ticket.refunded {
+            return Err(format!("Ticket {} was already refunded", ticket_id));
+        }
+
+        let event = self
+            .events
+            .get_mut(&ticket.event)
+            .ok_or(format!("Event {} does not exist", ticket.event))?;
+
+        if !event.cancelled {
+            return Err(format!("Event {} was not cancelled", ticket.event));
+        }
+
+        // Proceeds are only written once every check passed, rejected actions leave no trace.
+        let available = event
+            .proceeds
+            .get(&ticket.token)
+            .map_or(0, Proceeds::available);
+        if ticket.paid > available {
+            return Err(format!(
+                "Cannot refund {} {} for ticket {}, only {} is left for {}",
+                ticket.paid, ticket.token, ticket_id, available, ticket.event
+            ));
+        }
+
+        let payouts = match ticket.paid {
+            0 => vec![],
+            paid => vec![Payout {
+                token: ticket.token.clone(),
+                recipient: ticket.owner.clone(),
+                amount: paid,
+            }],
+        };
+        check_payouts(ctx, calldata, &payouts)?;
+
+        if let Some(proceeds) = event.proceeds.get_mut(&ticket.token) {
+            proceeds.refunded += ticket.paid;
+        }
+        ticket.refunded = true;
+
+        Ok(format!(
+            "Ticket {} refunded {} {} to {:?}",
+            ticket_id, ticket.paid, ticket.token, &ctx.caller
+        ))
+    }
+
+    pub fn withdraw(
+        &mut self,
+        ctx: &ExecutionContext,
+        calldata: &sdk::Calldata,
+        event_id: &EventId,
+        token: ContractName,
+        amount: u128,
+        recipient: Identity,
+    ) -> Result<String, String> {
+        self.check_owner(ctx)?;
+
+        let event = self
+            .events
+            .get_mut(event_id)
+            .ok_or(format!("Event {} does not exist", event_id))?;
+
+        let tx_ctx = calldata
+            .tx_ctx
+            .as_ref()
+            .ok_or("Missing transaction context")?;
+        event.check_withdrawal(tx_ctx)?;
+
+        let available = event.proceeds.get(&token).map_or(0, Proceeds::available);
+        if amount > available {
+            return Err(format!(
+                "Cannot withdraw {} {} from {}, only {} is available",
+                amount, token, event_id, available
+            ));
+        }
+
+        check_payouts(
+            ctx,
+            calldata,
+            &[Payout {
+                token: token.clone(),
+                recipient: recipient.clone(),
+                amount,
+            }],
+        )?;
+        if let Some(proceeds) = event.proceeds.get_mut(&token) {
+            proceeds.withdrawn += amount;
+        }
+
+        Ok(format!(
+            "{} {} of {} withdrawn to {:?}",
+            amount, token, event_id, recipient
+        ))
+    }
+
+    fn check_owner(&self, ctx: &ExecutionContext) -> Result<(), String> {
+        if ctx.caller != self.owner {
+            return Err(format!(
+                "Only the owner {:?} can do this, not {:?}",
+                self.owner, ctx.caller
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the action is the only one of the contract in the transaction which may
+    /// be paid for.
+    fn check_single_paid_action(
+        ctx: &ExecutionContext,
+        calldata: &sdk::Calldata,
+    ) -> Result<(), String> {
+        let other_paid = calldata.blobs.iter().any(|(index, blob)| {
+            *index != calldata.index
+                && blob.contract_name == ctx.contract_name
+                && borsh::from_slice::<TicketAppAction>(&blob.data.0)
+                    .is_ok_and(|action| action.requirements().payment != Need::Never)
+        });
+
+        if other_paid {
+            return Err(format!(
+                "Only one action of {} paid for is allowed per transaction",
+                ctx.contract_name
+            ));
         }
+
+        Ok(())
     }
 
+    /// Tokens `action` can be paid with: those of the current tier of the event a ticket is
+    /// bought for, or the token a transferred ticket was bought with. Blobs of other tokens are
+    /// never taken for its payment.
+    pub fn payment_tokens(
+        &self,
+        action: &TicketAppAction,
+        calldata: &sdk::Calldata,
+    ) -> BTreeSet<ContractName> {
+        match action {
+            TicketAppAction::BuyTicket { event_id, .. } => self
+                .events
+                .get(event_id)
+                .zip(calldata.tx_ctx.as_ref())
+                .map(|(event, tx_ctx)| event.current_tier(tx_ctx).1.keys().cloned().collect())
+                .unwrap_or_default(),
+            TicketAppAction::TransferTicket { ticket_id, .. } => self
+                .tickets
+                .get(*ticket_id as usize)
+                .map(|ticket| BTreeSet::from([ticket.token.clone()]))
+                .unwrap_or_default(),
+            _ => BTreeSet::new(),
+        }
+    }
+
+    /// Number of tickets still on sale, for each event.
+    pub fn remaining_tickets(&self) -> BTreeMap<EventId, u32> {
+        self.events
+            .iter()
+            .map(|(event_id, event)| (event_id.clone(), event.remaining()))
+            .collect()
+    }
+
+    /// Encodes the state as committed on-chain, which is also the commitment metadata the
+    /// guest decodes it from.
     pub fn as_bytes(&self) -> Result<Vec<u8>, Error> {
-        borsh::to_vec(self)
+        if let Some(error) = &self.decode_error {
+            return Err(Error::other(error.clone()));
+        }
+        match &self.migrated_from {
+            Some(commitment) => Ok(commitment.clone()),
+            None => self.encode(),
+        }
     }
 }
 
 impl From<sdk::StateCommitment> for TicketApp {
+    /// Commitments no known layout decodes, e.g. from a newer build, give a state every
+    /// action fails on, as the indexer cannot be handed an error here. Use
+    /// `TicketApp::decode` wherever one can.
     fn from(state: sdk::StateCommitment) -> Self {
-        borsh::from_slice(&state.0)
-            .map_err(|_| "Could not decode TicketApp state".to_string())
-            .unwrap()
+        TicketApp::decode(&state.0).unwrap_or_else(|e| TicketApp {
+            decode_error: Some(e),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyle_hydentity::HydentityAction;
+    use sdk::{BlobIndex, ContractAction};
+
+    use super::*;
+    use crate::event::{PriceTier, SaleBound, SaleWindow, STANDARD_TIER};
+    use crate::policy::FULL_PRICE;
+    use crate::public_signals::tests::{full_signals, revealed};
+
+    const EVENT: &str = "default";
+    const OWNER: &str = "organizer.hydentity";
+    const ALICE: &str = "alice.hydentity";
+    const BOB: &str = "bob.hydentity";
+
+    /// State whose only ticket was bought by alice for 80 hyllar, with the French discount.
+    fn sold_ticket() -> TicketApp {
+        let mut event = Event::new(BTreeMap::from([("hyllar".into(), 100)]), 10, None);
+        event.sold = 1;
+        event.collect(&"hyllar".into(), 80).unwrap();
+
+        TicketApp {
+            owner: OWNER.into(),
+            events: BTreeMap::from([(EVENT.to_string(), event)]),
+            tickets: vec![Ticket {
+                id: 0,
+                owner: ALICE.into(),
+                event: EVENT.to_string(),
+                tier: STANDARD_TIER.to_string(),
+                used: false,
+                purchased_at: BlockHeight(0),
+                paid: 80,
+                base_price: 100,
+                rate: 8_000,
+                token: "hyllar".into(),
+                refunded: false,
+            }],
+            identity_contracts: BTreeSet::from(["hydentity".into()]),
+            ..Default::default()
+        }
+    }
+
+    /// Calldata of `action` of `caller` at block `height`, followed by `blobs` and the
+    /// verification of the caller's identity.
+    fn calldata(
+        caller: &str,
+        height: u64,
+        action: &TicketAppAction,
+        blobs: Vec<sdk::Blob>,
+    ) -> sdk::Calldata {
+        let identity = HydentityAction::VerifyIdentity {
+            account: caller.to_string(),
+            nonce: 0,
+        }
+        .as_blob("hydentity".into());
+        let blobs: Vec<sdk::Blob> = [
+            vec![action.as_blob("ticket-app".into())],
+            blobs,
+            vec![identity],
+        ]
+        .concat();
+        sdk::Calldata {
+            identity: caller.into(),
+            index: BlobIndex(0),
+            tx_blob_count: blobs.len(),
+            blobs: blobs.into(),
+            tx_hash: Default::default(),
+            tx_ctx: Some(TxContext {
+                block_height: BlockHeight(height),
+                ..Default::default()
+            }),
+            private_input: vec![],
+        }
+    }
+
+    /// Applies `action` of `caller` at block `height`, followed by `blobs`.
+    fn apply(
+        state: &mut TicketApp,
+        caller: &str,
+        height: u64,
+        action: TicketAppAction,
+        blobs: Vec<sdk::Blob>,
+    ) -> Result<String, String> {
+        let calldata = calldata(caller, height, &action, blobs);
+        let (action, ctx) = sdk::utils::parse_raw_calldata::<TicketAppAction>(&calldata)?;
+        state.apply(action, &ctx, &calldata)
+    }
+
+    /// Transfer of hyllar paying the contract.
+    fn payment(amount: u128) -> sdk::Blob {
+        HyllarAction::Transfer {
+            recipient: "ticket-app".to_string(),
+            amount,
+        }
+        .as_blob("hyllar".into(), None, None)
+    }
+
+    /// Transfer of hyllar out of the contract, whose blob is at index 0.
+    fn payout(recipient: &str, amount: u128) -> sdk::Blob {
+        HyllarAction::Transfer {
+            recipient: recipient.to_string(),
+            amount,
+        }
+        .as_blob("hyllar".into(), Some(BlobIndex(0)), None)
+    }
+
+    fn proceeds(state: &TicketApp) -> Proceeds {
+        state.events[EVENT].proceeds[&ContractName::from("hyllar")].clone()
+    }
+
+    fn cancel(state: &mut TicketApp) {
+        let cancel = TicketAppAction::CancelEvent {
+            event_id: EVENT.to_string(),
+        };
+        apply(state, OWNER, 1, cancel, vec![]).unwrap();
+    }
+
+    fn withdraw(amount: u128) -> TicketAppAction {
+        TicketAppAction::Withdraw {
+            event_id: EVENT.to_string(),
+            token: "hyllar".into(),
+            amount,
+            recipient: OWNER.into(),
+        }
+    }
+
+    fn transfer_to(passport: Option<&[u8; 3]>) -> TicketAppAction {
+        TicketAppAction::TransferTicket {
+            ticket_id: 0,
+            recipient: BOB.into(),
+            recipient_passport: passport.map(|nationality| {
+                full_signals(&revealed(nationality, b"18"), "7", "1", &BOB.into())
+            }),
+        }
+    }
+
+    #[test]
+    fn refunds_tickets_of_cancelled_events_once() {
+        let mut state = sold_ticket();
+        let refund = || TicketAppAction::ClaimRefund { ticket_id: 0 };
+
+        assert!(apply(&mut state, ALICE, 1, refund(), vec![payout(ALICE, 80)]).is_err());
+
+        cancel(&mut state);
+        assert!(apply(&mut state, ALICE, 2, refund(), vec![payout(ALICE, 81)]).is_err());
+        assert!(apply(&mut state, ALICE, 2, refund(), vec![payout(BOB, 80)]).is_err());
+        assert!(apply(&mut state, ALICE, 2, refund(), vec![payout(ALICE, 80)]).is_ok());
+        assert_eq!(proceeds(&state).refunded, 80);
+
+        assert!(apply(&mut state, ALICE, 3, refund(), vec![payout(ALICE, 80)]).is_err());
+        assert_eq!(proceeds(&state).refunded, 80);
+        assert!(state.tickets[0].refunded);
+    }
+
+    #[test]
+    fn withdraws_proceeds_after_their_escrow_release() {
+        let mut state = sold_ticket();
+        let event = state.events.get_mut(EVENT).unwrap();
+        event.sale_window.end = Some(SaleBound::BlockHeight(10));
+        event.escrow_release = Some(SaleBound::BlockHeight(20));
+
+        assert!(apply(&mut state, OWNER, 19, withdraw(80), vec![payout(OWNER, 80)]).is_err());
+        assert_eq!(proceeds(&state).withdrawn, 0);
+
+        assert!(apply(&mut state, OWNER, 20, withdraw(81), vec![payout(OWNER, 81)]).is_err());
+        assert!(apply(&mut state, OWNER, 20, withdraw(80), vec![payout(ALICE, 80)]).is_err());
+        assert!(apply(&mut state, ALICE, 20, withdraw(80), vec![payout(OWNER, 80)]).is_err());
+        assert!(apply(&mut state, OWNER, 20, withdraw(80), vec![payout(OWNER, 80)]).is_ok());
+        assert_eq!(proceeds(&state).withdrawn, 80);
+        assert_eq!(proceeds(&state).available(), 0);
+    }
+
+    #[test]
+    fn keeps_proceeds_of_cancelled_events_for_refunds() {
+        let mut state = sold_ticket();
+        cancel(&mut state);
+
+        assert!(apply(&mut state, OWNER, 2, withdraw(80), vec![payout(OWNER, 80)]).is_err());
+        assert_eq!(proceeds(&state).withdrawn, 0);
+    }
+
+    #[test]
+    fn claws_back_discounts_recipients_are_not_entitled_to() {
+        // Without a passport the recipient pays the full price, 20 more.
+        let mut state = sold_ticket();
+        assert!(apply(&mut state, ALICE, 1, transfer_to(None), vec![]).is_err());
+        assert!(apply(&mut state, ALICE, 1, transfer_to(None), vec![payment(19)]).is_err());
+        assert!(apply(&mut state, ALICE, 1, transfer_to(None), vec![payment(20)]).is_ok());
+        assert_eq!(state.tickets[0].owner, BOB.into());
+        assert_eq!(state.tickets[0].paid, 100);
+        assert_eq!(state.tickets[0].rate, FULL_PRICE);
+        assert_eq!(proceeds(&state).collected, 100);
+
+        // A German recipient gets 10% off, 10 more than the French discount.
+        let mut state = sold_ticket();
+        let german = transfer_to(Some(b"DEU"));
+        assert!(apply(&mut state, ALICE, 1, german, vec![payment(10)]).is_ok());
+        assert_eq!(state.tickets[0].paid, 90);
+        assert_eq!(state.tickets[0].rate, 9_000);
+        assert_eq!(proceeds(&state).collected, 90);
+    }
+
+    #[test]
+    fn transfers_without_clawback_take_no_payment() {
+        let mut state = sold_ticket();
+        let french = || transfer_to(Some(b"FRA"));
+
+        assert!(apply(&mut state, ALICE, 1, french(), vec![payment(10)]).is_err());
+        assert_eq!(proceeds(&state).collected, 80);
+
+        assert!(apply(&mut state, ALICE, 1, french(), vec![]).is_ok());
+        assert_eq!(state.tickets[0].owner, BOB.into());
+        assert_eq!(state.tickets[0].paid, 80);
+        assert_eq!(proceeds(&state).collected, 80);
+    }
+
+    #[test]
+    fn takes_payments_in_the_tokens_of_the_action_only() {
+        let mut state = sold_ticket();
+        let event = state.events.get_mut(EVENT).unwrap();
+        event.price_tiers.push(PriceTier {
+            name: "early".to_string(),
+            window: SaleWindow {
+                start: None,
+                end: Some(SaleBound::BlockHeight(10)),
+            },
+            prices: BTreeMap::from([("early-token".into(), 50)]),
+        });
+        state.events.insert(
+            "other".to_string(),
+            Event::new(BTreeMap::from([("other-token".into(), 100)]), 10, None),
+        );
+        let buy = TicketAppAction::BuyTicket {
+            event_id: EVENT.to_string(),
+            public_signals: vec![],
+        };
+        let tokens = |height, action: &TicketAppAction| {
+            state.payment_tokens(action, &calldata(ALICE, height, action, vec![]))
+        };
+
+        assert_eq!(tokens(9, &buy), BTreeSet::from(["early-token".into()]));
+        assert_eq!(tokens(10, &buy), BTreeSet::from(["hyllar".into()]));
+        assert_eq!(
+            tokens(1, &transfer_to(None)),
+            BTreeSet::from(["hyllar".into()])
+        );
+        assert!(tokens(1, &withdraw(80)).is_empty());
+
+        // A clawback paid in a token of another event is not taken for the payment.
+        let other_token = HyllarAction::Transfer {
+            recipient: "ticket-app".to_string(),
+            amount: 20,
+        }
+        .as_blob("other-token".into(), None, None);
+        assert!(apply(&mut state, ALICE, 1, transfer_to(None), vec![other_token]).is_err());
+        assert_eq!(state.tickets[0].owner, ALICE.into());
     }
 }
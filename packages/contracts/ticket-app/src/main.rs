@@ -5,8 +5,9 @@ extern crate alloc;
 
 use alloc::vec::Vec;
 use sdk::{
-    guest::{execute, GuestEnv, Risc0Env},
-    Calldata,
+    guest::{GuestEnv, Risc0Env},
+    utils::as_hyle_output,
+    Calldata, ZkContract,
 };
 use ticket_app::TicketApp;
 
@@ -16,6 +17,18 @@ fn main() {
     let env = Risc0Env {};
     let (commitment_metadata, calldata): (Vec<u8>, Calldata) = env.read();
 
-    let output = execute::<TicketApp>(&commitment_metadata, &calldata);
+    // The metadata is the state as committed on-chain, in any known layout.
+    let mut state = TicketApp::decode(&commitment_metadata).expect("Failed to decode TicketApp");
+
+    let initial_state_commitment = state.commit();
+    let mut res = state.execute(&calldata);
+    let next_state_commitment = state.commit();
+
+    let output = as_hyle_output(
+        initial_state_commitment,
+        next_state_commitment,
+        &calldata,
+        &mut res,
+    );
     env.commit(&output);
 }
@@ -0,0 +1,238 @@
+use std::collections::BTreeSet;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+use hyle_hyllar::HyllarAction;
+use sdk::{caller::ExecutionContext, BlobIndex, ContractName, Identity};
+
+/// Amounts of one token the contract received and paid out for an event.
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default, PartialEq,
+)]
+pub struct Proceeds {
+    pub collected: u128,
+    pub withdrawn: u128,
+    /// Paid back to the holders of tickets for a cancelled event.
+    pub refunded: u128,
+}
+
+impl Proceeds {
+    pub fn collect(&mut self, amount: u128) -> Result<(), String> {
+        self.collected = self
+            .collected
+            .checked_add(amount)
+            .ok_or(format!("Collected amount overflows when adding {}", amount))?;
+
+        Ok(())
+    }
+
+    /// Amount still held by the contract, for the organizer or for refunds.
+    pub fn available(&self) -> u128 {
+        self.collected
+            .saturating_sub(self.withdrawn)
+            .saturating_sub(self.refunded)
+    }
+}
+
+/// Finds the hyllar action of the transaction paying the contract in one of `tokens`,
+/// wherever it sits among the blobs.
+pub fn find_payment(
+    calldata: &sdk::Calldata,
+    tokens: &BTreeSet<ContractName>,
+) -> Result<(ContractName, HyllarAction), String> {
+    calldata
+        .blobs
+        .iter()
+        .filter(|(index, blob)| *index != calldata.index && tokens.contains(&blob.contract_name))
+        .find_map(|(index, blob)| {
+            sdk::utils::parse_structured_blob::<HyllarAction>(&calldata.blobs, index)
+                // Transfers called by the contract pay out of it, they are not payments.
+                .filter(|payment| payment.data.caller != Some(calldata.index))
+                .map(|payment| (blob.contract_name.clone(), payment.data.parameters))
+        })
+        .ok_or(format!(
+            "No payment found in the transaction, expected a hyllar action of one of {:?}",
+            tokens
+        ))
+}
+
+/// Transfer the contract makes out of its own balance, through a blob it is the caller of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Payout {
+    pub token: ContractName,
+    pub recipient: Identity,
+    pub amount: u128,
+}
+
+/// Checks that the blobs of the transaction called by the contract are exactly `expected`.
+///
+/// Token contracts debit the caller's account, so any other blob naming the contract as its
+/// caller would spend its balance on behalf of whoever sent the transaction.
+pub fn check_payouts(
+    ctx: &ExecutionContext,
+    calldata: &sdk::Calldata,
+    expected: &[Payout],
+) -> Result<(), String> {
+    let mut pending = expected.to_vec();
+
+    for (index, blob) in calldata.blobs.iter() {
+        // Structured blobs start with their caller, whatever their parameters.
+        let caller: Option<Option<BlobIndex>> =
+            BorshDeserialize::deserialize(&mut blob.data.0.as_slice()).ok();
+        if *index == calldata.index || caller != Some(Some(calldata.index)) {
+            continue;
+        }
+
+        let transfer = sdk::utils::parse_structured_blob::<HyllarAction>(&calldata.blobs, index)
+            .map(|transfer| transfer.data.parameters);
+        let position = pending.iter().position(|payout| {
+            payout.token == blob.contract_name
+                && matches!(
+                    transfer,
+                    Some(HyllarAction::Transfer { ref recipient, amount })
+                        if recipient == &payout.recipient.0 && amount == payout.amount
+                )
+        });
+        let Some(position) = position else {
+            return Err(format!(
+                "Unexpected blob {} of {} called by {}",
+                index.0, blob.contract_name, ctx.contract_name
+            ));
+        };
+        pending.swap_remove(position);
+    }
+
+    if let Some(missing) = pending.first() {
+        return Err(format!(
+            "Missing transfer of {} {} from {} to {}",
+            missing.amount, missing.token, ctx.contract_name, missing.recipient.0
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sdk::ContractAction;
+
+    use super::*;
+    use crate::TicketAppAction;
+
+    /// Calldata of a ticket app action at index 0, followed by `blobs`.
+    fn calldata(blobs: Vec<sdk::Blob>) -> sdk::Calldata {
+        let action = TicketAppAction::ClaimRefund { ticket_id: 0 };
+        let blobs: Vec<sdk::Blob> = [vec![action.as_blob("ticket-app".into())], blobs].concat();
+        sdk::Calldata {
+            identity: "alice.hydentity".into(),
+            index: BlobIndex(0),
+            tx_blob_count: blobs.len(),
+            blobs: blobs.into(),
+            tx_hash: Default::default(),
+            tx_ctx: None,
+            private_input: vec![],
+        }
+    }
+
+    fn transfer(
+        token: &str,
+        caller: Option<BlobIndex>,
+        recipient: &str,
+        amount: u128,
+    ) -> sdk::Blob {
+        HyllarAction::Transfer {
+            recipient: recipient.to_string(),
+            amount,
+        }
+        .as_blob(token.into(), caller, None)
+    }
+
+    fn payout(recipient: &str, amount: u128) -> Payout {
+        Payout {
+            token: "hyllar".into(),
+            recipient: recipient.into(),
+            amount,
+        }
+    }
+
+    fn check(blobs: Vec<sdk::Blob>, expected: &[Payout]) -> Result<(), String> {
+        let calldata = calldata(blobs);
+        let (_, ctx) = sdk::utils::parse_raw_calldata::<TicketAppAction>(&calldata)?;
+        check_payouts(&ctx, &calldata, expected)
+    }
+
+    #[test]
+    fn accepts_expected_payouts() {
+        let contract = Some(BlobIndex(0));
+        assert_eq!(check(vec![], &[]), Ok(()));
+        assert_eq!(
+            check(
+                vec![transfer("hyllar", contract, "alice.hydentity", 10)],
+                &[payout("alice.hydentity", 10)]
+            ),
+            Ok(())
+        );
+        // Transfers the sender pays are none of the contract's business.
+        assert_eq!(
+            check(vec![transfer("hyllar", None, "ticket-app", 10)], &[]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_extra_blobs_called_by_the_contract() {
+        let contract = Some(BlobIndex(0));
+        assert!(check(vec![transfer("hyllar", contract, "bob.hydentity", 10)], &[]).is_err());
+        assert!(check(
+            vec![
+                transfer("hyllar", contract, "alice.hydentity", 10),
+                transfer("oranj", contract, "bob.hydentity", 10),
+            ],
+            &[payout("alice.hydentity", 10)]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn rejects_payouts_with_the_wrong_amount_or_recipient() {
+        let contract = Some(BlobIndex(0));
+        let expected = [payout("alice.hydentity", 10)];
+
+        assert!(check(
+            vec![transfer("hyllar", contract, "alice.hydentity", 11)],
+            &expected
+        )
+        .is_err());
+        assert!(check(
+            vec![transfer("hyllar", contract, "bob.hydentity", 10)],
+            &expected
+        )
+        .is_err());
+        assert!(check(
+            vec![transfer("oranj", contract, "alice.hydentity", 10)],
+            &expected
+        )
+        .is_err());
+        assert!(check(vec![], &expected).is_err());
+    }
+
+    #[test]
+    fn payments_are_not_payouts() {
+        let tokens = BTreeSet::from([ContractName::from("hyllar")]);
+
+        let paid = calldata(vec![transfer("hyllar", None, "ticket-app", 10)]);
+        assert_eq!(
+            find_payment(&paid, &tokens).map(|(token, _)| token),
+            Ok("hyllar".into())
+        );
+
+        let paid_out = calldata(vec![transfer(
+            "hyllar",
+            Some(BlobIndex(0)),
+            "alice.hydentity",
+            10,
+        )]);
+        assert!(find_payment(&paid_out, &tokens).is_err());
+    }
+}
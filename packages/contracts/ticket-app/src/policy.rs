@@ -0,0 +1,121 @@
+use std::collections::BTreeSet;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::SANCTIONED_COUNTRIES;
+use crate::countries::{Country, Region};
+use crate::eligibility::Eligibility;
+use crate::public_signals::{missing_nationality, PassportSignals};
+
+/// Share of the price paid by buyers no rule applies to, in basis points.
+pub const FULL_PRICE: u128 = 10_000;
+
+/// Prefix of the errors returned when a sale is refused for compliance reasons, so that
+/// clients can tell them apart from other failures.
+pub const COMPLIANCE_ERROR: &str = "E_COMPLIANCE";
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DiscountRule {
+    /// Nationalities of the buyers the rule applies to.
+    pub audience: Eligibility,
+    /// Share of the price paid, in basis points.
+    pub rate: u128,
+}
+
+/// Rules events start with, until the organizer changes them. Countries come before the
+/// EU, so that French buyers get their own discount rather than the EU one.
+pub fn default_discount_rules() -> Vec<DiscountRule> {
+    let mut rules: Vec<DiscountRule> = ["TWN", "FRA"]
+        .into_iter()
+        .filter_map(Country::from_code)
+        .map(|country| DiscountRule {
+            audience: Eligibility::Country(country),
+            rate: 8_000,
+        })
+        .collect();
+    rules.push(DiscountRule {
+        audience: Eligibility::Region(Region::Eu),
+        rate: 9_000,
+    });
+    rules
+}
+
+/// Checks rules before they are stored, so that `discount` never charges more than the price.
+pub fn validate_discount_rules(rules: &[DiscountRule]) -> Result<(), String> {
+    for rule in rules {
+        if rule.rate > FULL_PRICE {
+            return Err(format!(
+                "Discount rate should be at most {} basis points but was {} for {}",
+                FULL_PRICE, rule.rate, rule.audience
+            ));
+        }
+        rule.audience.validate()?;
+    }
+
+    Ok(())
+}
+
+/// Share of the price, in basis points, paid by buyers of `nationality`. The first matching
+/// rule wins.
+pub fn discount(rules: &[DiscountRule], nationality: Option<Country>) -> u128 {
+    let Some(nationality) = nationality else {
+        return FULL_PRICE;
+    };
+
+    rules
+        .iter()
+        .find(|rule| rule.audience.contains(nationality))
+        .map_or(FULL_PRICE, |rule| rule.rate)
+}
+
+/// Nationalities sales are refused to until the organizer changes them.
+pub fn default_denylist() -> BTreeSet<Country> {
+    SANCTIONED_COUNTRIES
+        .iter()
+        .filter_map(|code| Country::from_code(code))
+        .collect()
+}
+
+/// Checks that tickets may be sold to the holder of `passport`.
+///
+/// Buyers must disclose their nationality as soon as some are denied, or they could hide it.
+pub fn check_compliance(
+    denylist: &BTreeSet<Country>,
+    passport: Option<&PassportSignals>,
+) -> Result<(), String> {
+    if denylist.is_empty() {
+        return Ok(());
+    }
+
+    match passport.and_then(|passport| passport.nationality) {
+        Some(nationality) if denylist.contains(&nationality) => Err(format!(
+            "{}: tickets cannot be sold to {} nationals",
+            COMPLIANCE_ERROR,
+            nationality.name()
+        )),
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "{}: {}",
+            COMPLIANCE_ERROR,
+            missing_nationality(passport)
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_rules_give_every_discount() {
+        let rules = default_discount_rules();
+        let rate = |code| discount(&rules, Country::from_code(code));
+
+        assert_eq!(rate("FRA"), 8_000);
+        assert_eq!(rate("TWN"), 8_000);
+        assert_eq!(rate("DEU"), 9_000);
+        assert_eq!(rate("USA"), FULL_PRICE);
+        assert_eq!(discount(&rules, None), FULL_PRICE);
+    }
+}
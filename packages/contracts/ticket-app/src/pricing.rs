@@ -0,0 +1,75 @@
+use sdk::{ContractName, TxContext};
+use serde::Serialize;
+
+use crate::countries::Country;
+use crate::event::Event;
+use crate::policy::{discount, FULL_PRICE};
+
+/// Price a buyer pays for a ticket, as computed by the contract.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Quote {
+    pub tier: String,
+    pub base_price: u128,
+    /// Share of `base_price` paid, in basis points.
+    pub rate: u128,
+    pub price: u128,
+}
+
+/// Price of a ticket for `event`, paid in `token` by a buyer of `nationality`, at the point
+/// of `tx_ctx`.
+///
+/// The contract charges exactly this amount, so clients should quote through it too.
+pub fn quote(
+    nationality: Option<Country>,
+    event: &Event,
+    token: &ContractName,
+    tx_ctx: &TxContext,
+) -> Result<Quote, String> {
+    let (tier, base_price) = event.base_price(tx_ctx, token)?;
+    let rate = discount(&event.discount_rules, nationality);
+
+    Ok(Quote {
+        tier: tier.to_string(),
+        base_price,
+        rate,
+        price: apply_rate(base_price, rate)?,
+    })
+}
+
+/// Share of `price` paid at `rate` basis points, rounded up so that a discount never
+/// takes more than its rate off the price.
+pub fn apply_rate(price: u128, rate: u128) -> Result<u128, String> {
+    price
+        .checked_mul(rate)
+        .map(|amount| amount.div_ceil(FULL_PRICE))
+        .ok_or(format!(
+            "Price {} overflows at a rate of {} basis points",
+            price, rate
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_rate_in_basis_points() {
+        assert_eq!(apply_rate(200, FULL_PRICE), Ok(200));
+        assert_eq!(apply_rate(200, 5_000), Ok(100));
+        assert_eq!(apply_rate(200, 0), Ok(0));
+        assert_eq!(apply_rate(0, 5_000), Ok(0));
+    }
+
+    #[test]
+    fn rounds_discounted_prices_up() {
+        assert_eq!(apply_rate(10, 3_333), Ok(4));
+        assert_eq!(apply_rate(1, 1), Ok(1));
+        assert_eq!(apply_rate(3, 5_000), Ok(2));
+    }
+
+    #[test]
+    fn rejects_overflowing_prices() {
+        assert!(apply_rate(u128::MAX, 2).is_err());
+        assert_eq!(apply_rate(u128::MAX, 1), Ok(u128::MAX.div_ceil(FULL_PRICE)));
+    }
+}
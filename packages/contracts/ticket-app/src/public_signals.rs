@@ -0,0 +1,408 @@
+use std::ops::Range;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use sdk::Identity;
+
+use crate::countries::Country;
+
+/// Modulus of the BN254 scalar field, big-endian.
+const FIELD_MODULUS: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29, 0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x28, 0x33, 0xe8, 0x48, 0x79, 0xb9, 0x70, 0x91, 0x43, 0xe1, 0xf5, 0x93, 0xf0, 0x00, 0x00, 0x01,
+];
+
+/// The circuit packs 31 bytes of revealed data in each field element, little-endian.
+const BYTES_PER_FIELD: usize = 31;
+
+// Position of each value in the public signals of Self's `vc_and_disclose` circuit, as listed
+// by `CIRCUIT_CONSTANTS` in @selfxyz/core 0.0.24, which the client generates proofs with:
+// revealed data packed 0..3, forbidden countries packed 3..6, nullifier 6, attestation id 7,
+// merkle root 8, current date 9..15, passport number, name and date of birth, and name and
+// year of birth SMT roots 15..18, scope 18 and user identifier 19.
+const REVEALED_DATA_PACKED: Range<usize> = 0..3;
+const NULLIFIER: usize = 6;
+const SCOPE: usize = 18;
+const USER_IDENTIFIER: usize = 19;
+
+// Position of each field in the revealed passport data, which follows the MRZ.
+const NAME: Range<usize> = 5..44;
+const NATIONALITY: Range<usize> = 54..57;
+const EXPIRY_DATE: Range<usize> = 65..71;
+const OLDER_THAN: Range<usize> = 88..90;
+
+/// Typed view over the public signals of a passport disclosure proof.
+///
+/// Fields the holder chose not to disclose are `None`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PassportSignals {
+    /// Holder's nationality, when disclosed and known.
+    pub nationality: Option<Country>,
+    /// Code of the holder's nationality when disclosed but unknown to the contract, in which
+    /// case `nationality` is `None`.
+    pub unknown_nationality: Option<String>,
+    /// Holder's name as printed in the MRZ, with fillers turned into spaces.
+    pub name: Option<String>,
+    /// Minimum age the proof attests the holder has reached.
+    pub older_than: Option<u8>,
+    /// Passport expiry date, as `YYMMDD`.
+    pub expiry_date: Option<String>,
+    /// Unique per passport and scope, big-endian.
+    pub nullifier: [u8; 32],
+    /// Scope the proof was generated for, when the circuit exposes it.
+    pub scope: Option<[u8; 32]>,
+    /// Identifier of the user the proof was generated for, when the circuit exposes it. See
+    /// `user_identifier`.
+    pub user_identifier: Option<[u8; 32]>,
+}
+
+impl PassportSignals {
+    pub fn decode(public_signals: &[String]) -> Result<Self, String> {
+        let revealed = unpack_revealed_data(public_signals)?;
+
+        // Codes shorter than three letters, like `D` for Germany, are padded with fillers.
+        let code = String::from_utf8_lossy(&revealed[NATIONALITY]);
+        let code = code.trim_end_matches('<');
+        let nationality = Country::from_code(code);
+        let unknown_nationality = Some(code)
+            .filter(|_| nationality.is_none() && revealed[NATIONALITY] != [0; 3])
+            .map(str::to_string);
+
+        let name = revealed[NAME]
+            .iter()
+            .map(|&b| if b == b'<' { ' ' } else { b as char })
+            .collect::<String>()
+            .trim_matches(|c: char| c == ' ' || c == '\0')
+            .to_string();
+
+        let expiry_date = Some(&revealed[EXPIRY_DATE])
+            .filter(|date| date.iter().all(u8::is_ascii_digit))
+            .map(|date| date.iter().map(|&b| b as char).collect());
+
+        let older_than = match &revealed[OLDER_THAN] {
+            [0, 0] => None,
+            [tens, units] if tens.is_ascii_digit() && units.is_ascii_digit() => {
+                Some((tens - b'0') * 10 + (units - b'0')).filter(|&age| age > 0)
+            }
+            _ => return Err("Passport proof has a malformed minimum age".to_string()),
+        };
+
+        let nullifier = parse_field_element(
+            public_signals
+                .get(NULLIFIER)
+                .ok_or("Passport proof is missing the nullifier signal")?,
+        )?;
+
+        let scope = public_signals
+            .get(SCOPE)
+            .map(|signal| parse_field_element(signal))
+            .transpose()?;
+
+        let user_identifier = public_signals
+            .get(USER_IDENTIFIER)
+            .map(|signal| parse_field_element(signal))
+            .transpose()?;
+
+        Ok(PassportSignals {
+            nationality,
+            unknown_nationality,
+            name: Some(name).filter(|name| !name.is_empty()),
+            older_than,
+            expiry_date,
+            nullifier,
+            scope,
+            user_identifier,
+        })
+    }
+
+    /// Checks that the proof was generated for `identity`, so that it cannot be replayed by
+    /// another identity holding a copy of it.
+    pub fn check_user(&self, identity: &Identity) -> Result<(), String> {
+        match self.user_identifier {
+            Some(user) if user == user_identifier(identity) => Ok(()),
+            Some(_) => Err(format!(
+                "Passport proof was generated for another user than {}",
+                identity
+            )),
+            None => Err("Passport proof does not expose its user identifier".to_string()),
+        }
+    }
+}
+
+/// Why rules cannot check the nationality of the holder of `passport`, which has none known.
+pub fn missing_nationality(passport: Option<&PassportSignals>) -> String {
+    match passport {
+        None => "no passport proof discloses the holder's nationality".to_string(),
+        Some(PassportSignals {
+            unknown_nationality: Some(code),
+            ..
+        }) => format!("the passport nationality {:?} is not a known code", code),
+        Some(_) => "the passport proof should disclose the holder's nationality".to_string(),
+    }
+}
+
+/// User identifier proofs for `identity` are generated with, big-endian.
+///
+/// Self takes it as a UUID, so it is the first 16 bytes of the SHA-256 of the identity,
+/// with the version and variant bits of a UUIDv8. The client derives it the same way.
+pub fn user_identifier(identity: &Identity) -> [u8; 32] {
+    let hash = Sha256::digest(identity.0.as_bytes());
+
+    let mut uuid = [0u8; 16];
+    uuid.copy_from_slice(&hash[..16]);
+    uuid[6] = (uuid[6] & 0x0f) | 0x80;
+    uuid[8] = (uuid[8] & 0x3f) | 0x80;
+
+    let mut element = [0u8; 32];
+    element[16..].copy_from_slice(&uuid);
+    element
+}
+
+fn unpack_revealed_data(public_signals: &[String]) -> Result<Vec<u8>, String> {
+    let packed = public_signals
+        .get(REVEALED_DATA_PACKED)
+        .ok_or("Passport proof is missing the revealed data signals")?;
+
+    let mut revealed = Vec::with_capacity(packed.len() * BYTES_PER_FIELD);
+    for signal in packed {
+        let element = parse_field_element(signal)?;
+        revealed.extend(element.iter().rev().take(BYTES_PER_FIELD));
+    }
+
+    Ok(revealed)
+}
+
+/// Parses a decimal public signal into its canonical big-endian encoding.
+pub fn parse_field_element(signal: &str) -> Result<[u8; 32], String> {
+    if signal.is_empty() {
+        return Err("Public signal should not be empty".to_string());
+    }
+
+    let mut element = [0u8; 32];
+    for c in signal.bytes() {
+        if !c.is_ascii_digit() {
+            return Err(format!("Public signal {} is not a decimal number", signal));
+        }
+
+        let mut carry = (c - b'0') as u32;
+        for byte in element.iter_mut().rev() {
+            let value = *byte as u32 * 10 + carry;
+            *byte = value as u8;
+            carry = value >> 8;
+        }
+        if carry != 0 {
+            return Err(format!("Public signal {} does not fit in 256 bits", signal));
+        }
+    }
+
+    if element >= FIELD_MODULUS {
+        return Err(format!("Public signal {} is not a field element", signal));
+    }
+
+    Ok(element)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::frontend_data::create_mock_fe_data;
+
+    /// Decimal form of a big-endian field element.
+    fn to_decimal(mut element: [u8; 32]) -> String {
+        let mut digits = Vec::new();
+        while element.iter().any(|&b| b != 0) {
+            let mut remainder = 0u32;
+            for byte in element.iter_mut() {
+                let value = (remainder << 8) | *byte as u32;
+                *byte = (value / 10) as u8;
+                remainder = value % 10;
+            }
+            digits.push(b'0' + remainder as u8);
+        }
+        if digits.is_empty() {
+            digits.push(b'0');
+        }
+        digits.iter().rev().map(|&d| d as char).collect()
+    }
+
+    /// Public signals revealing `revealed`, packed like the circuit does, with `nullifier`.
+    pub(crate) fn signals(revealed: &[u8], nullifier: &str) -> Vec<String> {
+        let mut data = revealed.to_vec();
+        data.resize(REVEALED_DATA_PACKED.len() * BYTES_PER_FIELD, 0);
+
+        let mut signals: Vec<String> = data
+            .chunks(BYTES_PER_FIELD)
+            .map(|chunk| {
+                let mut element = [0u8; 32];
+                for (byte, &value) in element.iter_mut().rev().zip(chunk) {
+                    *byte = value;
+                }
+                to_decimal(element)
+            })
+            .collect();
+        signals.resize(NULLIFIER, "0".to_string());
+        signals.push(nullifier.to_string());
+        signals
+    }
+
+    /// Public signals with the full layout of the circuit, for `user` within `scope`.
+    pub(crate) fn full_signals(
+        revealed: &[u8],
+        nullifier: &str,
+        scope: &str,
+        user: &Identity,
+    ) -> Vec<String> {
+        let mut signals = signals(revealed, nullifier);
+        // Attestation id 1 stands for passports.
+        signals.push("1".to_string());
+        signals.resize(SCOPE, "0".to_string());
+        signals.push(scope.to_string());
+        signals.push(to_decimal(user_identifier(user)));
+        signals
+    }
+
+    pub(crate) fn revealed(nationality: &[u8; 3], older_than: &[u8; 2]) -> Vec<u8> {
+        let mut revealed = vec![0u8; OLDER_THAN.end];
+        revealed[NAME][..10].copy_from_slice(b"DOE<<JOHN<");
+        revealed[NATIONALITY].copy_from_slice(nationality);
+        revealed[EXPIRY_DATE].copy_from_slice(b"300101");
+        revealed[OLDER_THAN].copy_from_slice(older_than);
+        revealed
+    }
+
+    #[test]
+    fn decodes_mock_passport() {
+        let passport = create_mock_fe_data().passport;
+        let signals = PassportSignals::decode(&passport.public_signal).unwrap();
+
+        assert_eq!(signals.nationality, Country::from_code("TWN"));
+        assert_eq!(signals.older_than, Some(18));
+        assert_eq!(
+            signals.nullifier,
+            parse_field_element(&passport.public_signal[NULLIFIER]).unwrap()
+        );
+        assert_eq!(signals.scope, None);
+        assert_eq!(signals.user_identifier, None);
+    }
+
+    #[test]
+    fn decodes_full_layout() {
+        let alice = Identity::from("alice.hydentity");
+        let public_signals = full_signals(&revealed(b"FRA", b"18"), "7", "42", &alice);
+        assert_eq!(public_signals.len(), USER_IDENTIFIER + 1);
+
+        let signals = PassportSignals::decode(&public_signals).unwrap();
+        assert_eq!(signals.nationality, Country::from_code("FRA"));
+        assert_eq!(signals.nullifier[31], 7);
+        assert_eq!(signals.scope.map(|scope| scope[31]), Some(42));
+        assert_eq!(signals.user_identifier, Some(user_identifier(&alice)));
+    }
+
+    #[test]
+    fn binds_proofs_to_their_user() {
+        let alice = Identity::from("alice.hydentity");
+        let signals =
+            PassportSignals::decode(&full_signals(&revealed(b"FRA", b"18"), "7", "42", &alice))
+                .unwrap();
+
+        assert_eq!(signals.check_user(&alice), Ok(()));
+        assert!(signals.check_user(&"bob.hydentity".into()).is_err());
+
+        let mock = PassportSignals::decode(&create_mock_fe_data().passport.public_signal).unwrap();
+        assert!(mock.check_user(&alice).is_err());
+    }
+
+    #[test]
+    fn derives_uuid_user_identifiers() {
+        let user = user_identifier(&"alice.hydentity".into());
+        assert_eq!(user[..16], [0; 16]);
+        // UUID 60ecd173-2da9-8416-b6e7-6f7584095f64, as the client derives it.
+        assert_eq!(
+            user[16..],
+            [
+                0x60, 0xec, 0xd1, 0x73, 0x2d, 0xa9, 0x84, 0x16, 0xb6, 0xe7, 0x6f, 0x75, 0x84, 0x09,
+                0x5f, 0x64
+            ]
+        );
+        // Version 8 and RFC 9562 variant.
+        assert_eq!(user[16 + 6] >> 4, 8);
+        assert_eq!(user[16 + 8] >> 6, 0b10);
+        assert_ne!(user, user_identifier(&"bob.hydentity".into()));
+    }
+
+    #[test]
+    fn decodes_revealed_fields() {
+        let signals = PassportSignals::decode(&signals(&revealed(b"D<<", b"21"), "7")).unwrap();
+
+        assert_eq!(signals.nationality, Country::from_code("DEU"));
+        assert_eq!(signals.name.as_deref(), Some("DOE  JOHN"));
+        assert_eq!(signals.expiry_date.as_deref(), Some("300101"));
+        assert_eq!(signals.older_than, Some(21));
+        assert_eq!(signals.nullifier[31], 7);
+    }
+
+    #[test]
+    fn decodes_icao_nationalities() {
+        let decode = |code| PassportSignals::decode(&signals(&revealed(code, b"18"), "1")).unwrap();
+
+        assert_eq!(decode(b"XXA").nationality, Country::from_code("XXA"));
+        assert_eq!(decode(b"GBN").nationality, Country::from_code("GBN"));
+        assert_eq!(decode(b"RKS").nationality, Country::from_code("XKX"));
+    }
+
+    #[test]
+    fn tells_unknown_nationalities_apart() {
+        let unknown = PassportSignals::decode(&signals(&revealed(b"ZZZ", b"18"), "1")).unwrap();
+        assert_eq!(unknown.nationality, None);
+        assert_eq!(unknown.unknown_nationality.as_deref(), Some("ZZZ"));
+        assert!(missing_nationality(Some(&unknown)).contains("ZZZ"));
+
+        let undisclosed = PassportSignals::decode(&signals(&[], "1")).unwrap();
+        assert_eq!(undisclosed.unknown_nationality, None);
+        assert!(missing_nationality(Some(&undisclosed)).contains("should disclose"));
+    }
+
+    #[test]
+    fn leaves_undisclosed_fields_out() {
+        let signals = PassportSignals::decode(&signals(&[], "1")).unwrap();
+
+        assert_eq!(signals.nationality, None);
+        assert_eq!(signals.name, None);
+        assert_eq!(signals.expiry_date, None);
+        assert_eq!(signals.older_than, None);
+    }
+
+    #[test]
+    fn rejects_malformed_signals() {
+        assert!(PassportSignals::decode(&signals(&revealed(b"FRA", b"1x"), "1")).is_err());
+
+        let mut missing_nullifier = signals(&[], "1");
+        missing_nullifier.truncate(NULLIFIER);
+        assert!(PassportSignals::decode(&missing_nullifier).is_err());
+    }
+
+    #[test]
+    fn parses_field_elements() {
+        assert_eq!(parse_field_element("0").unwrap(), [0; 32]);
+
+        let mut element = [0u8; 32];
+        element[30] = 1;
+        element[31] = 2;
+        assert_eq!(parse_field_element("258").unwrap(), element);
+
+        let mut largest = FIELD_MODULUS;
+        largest[31] -= 1;
+        assert_eq!(parse_field_element(&to_decimal(largest)).unwrap(), largest);
+    }
+
+    #[test]
+    fn rejects_invalid_field_elements() {
+        assert!(parse_field_element("").is_err());
+        assert!(parse_field_element("-1").is_err());
+        assert!(parse_field_element("12a").is_err());
+        assert!(parse_field_element(&to_decimal(FIELD_MODULUS)).is_err());
+        assert!(parse_field_element(&"9".repeat(78)).is_err());
+    }
+}
@@ -0,0 +1,293 @@
+//! Versioned layout of the state committed on-chain.
+//!
+//! Commitments start with `STATE_MAGIC` and the layout version, followed by the borsh
+//! encoding of the state. Changing the fields of `TicketApp` means bumping `STATE_VERSION`,
+//! keeping the previous layout here as `TicketAppV<n>`, and migrating it in `decode`, so that
+//! the contract can be upgraded without being registered again.
+//!
+//! The legacy layout had no owner nor passport verification. States in it are migrated with
+//! the parameters the contract was built with, set by `build.rs` from `data/migration.txt`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use borsh::{io::Error, BorshDeserialize, BorshSerialize};
+
+use sdk::{BlockHeight, ContractName, Identity};
+
+use crate::event::{Event, EventId, STANDARD_TIER};
+use crate::policy::{default_denylist, FULL_PRICE};
+use crate::public_signals::parse_field_element;
+use crate::verifier::PassportVerifyingKey;
+use crate::{Ticket, TicketApp};
+
+include!(concat!(env!("OUT_DIR"), "/migration.rs"));
+
+/// Starts every versioned commitment. The legacy layout starts with the length of a contract
+/// name, which can never be this large, so the two cannot be mistaken for one another.
+pub const STATE_MAGIC: [u8; 4] = *b"TKAP";
+
+/// Layout `TicketApp` is committed with.
+pub const STATE_VERSION: u16 = 1;
+
+/// Event the tickets of the legacy layout, which had a single implicit one, are moved to.
+pub const LEGACY_EVENT: &str = "default";
+
+/// Identity contract the legacy layout was deployed with, trusted after its migration.
+pub const LEGACY_IDENTITY_CONTRACT: &str = "hydentity";
+
+/// Layout committed before versioning: a single event with unlimited tickets, without owner.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct TicketAppV0 {
+    pub ticket_price: (ContractName, u128),
+    pub tickets: Vec<Identity>,
+}
+
+/// Parameters the legacy layout lacks, given to the states migrated from it.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub owner: Identity,
+    pub passport_vk: PassportVerifyingKey,
+    pub passport_scope: [u8; 32],
+}
+
+impl Migration {
+    /// Parameters the contract was built with. The guest migrates with them, so a migrated
+    /// state cannot be given to whoever proves the first transaction on it.
+    pub fn pinned() -> Result<Self, String> {
+        let (Some(owner), Some(passport_vk), Some(passport_scope)) = (
+            MIGRATION_OWNER,
+            MIGRATION_PASSPORT_VK,
+            MIGRATION_PASSPORT_SCOPE,
+        ) else {
+            return Err(
+                "Legacy TicketApp state can only be migrated by a build with owner, \
+                 passport_vk and passport_scope set in data/migration.txt"
+                    .to_string(),
+            );
+        };
+
+        Ok(Migration {
+            owner: owner.into(),
+            passport_vk: serde_json::from_str(passport_vk)
+                .map_err(|e| format!("Could not parse the migration passport vk: {}", e))?,
+            passport_scope: parse_field_element(passport_scope)?,
+        })
+    }
+}
+
+impl TicketAppV0 {
+    /// Amounts paid were not recorded, so migrated tickets cannot be refunded.
+    pub fn migrate(self, migration: Migration) -> TicketApp {
+        let (token, price) = self.ticket_price;
+
+        let mut event = Event::new(BTreeMap::from([(token.clone(), price)]), u32::MAX, None);
+        event.sold = self.tickets.len() as u32;
+
+        let tickets = self
+            .tickets
+            .into_iter()
+            .enumerate()
+            .map(|(id, owner)| Ticket {
+                id: id as u64,
+                owner,
+                event: LEGACY_EVENT.to_string(),
+                tier: STANDARD_TIER.to_string(),
+                used: false,
+                purchased_at: BlockHeight(0),
+                paid: 0,
+                base_price: price,
+                rate: FULL_PRICE,
+                token: token.clone(),
+                refunded: false,
+            })
+            .collect();
+
+        TicketApp {
+            owner: migration.owner,
+            events: BTreeMap::from([(EventId::from(LEGACY_EVENT), event)]),
+            tickets,
+            passport_vk: Some(migration.passport_vk),
+            passport_scope: Some(migration.passport_scope),
+            identity_contracts: BTreeSet::from([ContractName::from(LEGACY_IDENTITY_CONTRACT)]),
+            denylist: default_denylist(),
+            migrated_from: None,
+            decode_error: None,
+        }
+    }
+}
+
+impl TicketApp {
+    /// Encodes the state in the current layout, as committed on-chain.
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = STATE_MAGIC.to_vec();
+        STATE_VERSION.serialize(&mut bytes)?;
+        self.serialize(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Decodes a commitment of any known layout, migrating it to the current one with the
+    /// pinned `Migration`.
+    ///
+    /// A migrated state keeps the commitment it was decoded from, until the next
+    /// transaction replaces it with one in the current layout.
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        Self::decode_with(bytes, Migration::pinned)
+    }
+
+    /// Decodes like `decode`, migrating legacy states with the parameters of `migration`.
+    pub fn decode_with(
+        bytes: &[u8],
+        migration: impl FnOnce() -> Result<Migration, String>,
+    ) -> Result<Self, String> {
+        let Some(mut versioned) = bytes.strip_prefix(&STATE_MAGIC) else {
+            let legacy = borsh::from_slice::<TicketAppV0>(bytes)
+                .map_err(|e| format!("Could not decode legacy TicketApp state: {}", e))?;
+            return Ok(TicketApp {
+                migrated_from: Some(bytes.to_vec()),
+                ..legacy.migrate(migration()?)
+            });
+        };
+
+        let version = u16::deserialize(&mut versioned)
+            .map_err(|e| format!("Could not decode TicketApp state version: {}", e))?;
+
+        match version {
+            STATE_VERSION => borsh::from_slice::<TicketApp>(versioned).map_err(|e| {
+                format!(
+                    "Could not decode TicketApp state of version {}: {}",
+                    version, e
+                )
+            }),
+            _ => Err(format!(
+                "TicketApp state has version {} but only versions up to {} are known",
+                version, STATE_VERSION
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sdk::ZkContract;
+
+    use super::*;
+
+    fn migration() -> Migration {
+        Migration {
+            owner: "organizer.hydentity".into(),
+            passport_vk: PassportVerifyingKey {
+                vk_alpha_1: vec![],
+                vk_beta_2: vec![],
+                vk_gamma_2: vec![],
+                vk_delta_2: vec![],
+                ic: vec![],
+            },
+            passport_scope: [7; 32],
+        }
+    }
+
+    fn legacy() -> Vec<u8> {
+        borsh::to_vec(&TicketAppV0 {
+            ticket_price: ("hyllar".into(), 10),
+            tickets: vec!["alice.hydentity".into(), "bob.hydentity".into()],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn migrates_legacy_layout() {
+        let state = TicketApp::decode_with(&legacy(), || Ok(migration())).unwrap();
+
+        assert_eq!(state.owner, migration().owner);
+        assert_eq!(state.passport_vk, Some(migration().passport_vk));
+        assert_eq!(state.passport_scope, Some([7; 32]));
+
+        let event = &state.events[LEGACY_EVENT];
+        assert_eq!(event.prices[&ContractName::from("hyllar")], 10);
+        assert_eq!(event.sold, 2);
+
+        assert_eq!(state.tickets.len(), 2);
+        assert_eq!(state.tickets[1].id, 1);
+        assert_eq!(state.tickets[1].owner, "bob.hydentity".into());
+        assert_eq!(state.tickets[1].event, LEGACY_EVENT);
+        assert_eq!(state.tickets[1].base_price, 10);
+        assert_eq!(state.tickets[1].paid, 0);
+    }
+
+    #[test]
+    fn migrated_state_commits_to_its_legacy_layout() {
+        let mut state = TicketApp::decode_with(&legacy(), || Ok(migration())).unwrap();
+        assert_eq!(state.commit().0, legacy());
+
+        // The next commitment is in the current layout, without the legacy one.
+        state.migrated_from = None;
+        let commitment = state.commit().0;
+        assert!(commitment.starts_with(&STATE_MAGIC));
+
+        let decoded = TicketApp::decode(&commitment).unwrap();
+        assert_eq!(decoded.migrated_from, None);
+        assert_eq!(decoded.encode().unwrap(), commitment);
+    }
+
+    #[test]
+    fn failed_transaction_keeps_legacy_commitment() {
+        let mut state = TicketApp::decode_with(&legacy(), || Ok(migration())).unwrap();
+        let calldata = sdk::Calldata {
+            identity: "alice.hydentity".into(),
+            index: sdk::BlobIndex(0),
+            blobs: vec![sdk::Blob {
+                contract_name: "ticket_app".into(),
+                data: sdk::BlobData(vec![0xff]),
+            }]
+            .into(),
+            tx_blob_count: 1,
+            tx_hash: Default::default(),
+            tx_ctx: None,
+            private_input: vec![],
+        };
+
+        assert!(state.execute(&calldata).is_err());
+        assert_eq!(state.commit().0, legacy());
+    }
+
+    #[test]
+    fn undecodable_commitment_gives_a_failing_state() {
+        let state = TicketApp::from(sdk::StateCommitment(vec![1, 2, 3]));
+        assert_eq!(
+            state.decode_error,
+            Some(TicketApp::decode(&[1, 2, 3]).unwrap_err())
+        );
+        assert!(state.as_bytes().is_err());
+    }
+
+    #[test]
+    fn legacy_layout_needs_migration_parameters() {
+        let result = TicketApp::decode_with(&legacy(), || Err("no migration".to_string()));
+        assert_eq!(result.unwrap_err(), "no migration");
+    }
+
+    #[test]
+    fn current_layout_needs_no_migration() {
+        let state = TicketApp::decode_with(&legacy(), || Ok(migration())).unwrap();
+        let commitment = TicketApp {
+            migrated_from: None,
+            ..state
+        }
+        .encode()
+        .unwrap();
+
+        let decoded =
+            TicketApp::decode_with(&commitment, || panic!("current layout was migrated")).unwrap();
+        assert_eq!(decoded.owner, migration().owner);
+        assert_eq!(decoded.tickets.len(), 2);
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let mut commitment = STATE_MAGIC.to_vec();
+        commitment.extend((STATE_VERSION + 1).to_le_bytes());
+        assert!(TicketApp::decode(&commitment).is_err());
+
+        assert!(TicketApp::decode(&[1, 2, 3]).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use risc0_groth16::{ProofJson, PublicInputsJson, Verifier, VerifyingKeyJson};
+use serde::{Deserialize, Serialize};
+
+use crate::frontend_data::Groth16Proof;
+
+/// Verification key of the passport disclosure circuit.
+///
+/// Field names follow snarkjs' `verification_key.json`, so the key can be
+/// loaded straight from the file produced by the circuit setup.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PassportVerifyingKey {
+    pub vk_alpha_1: Vec<String>,
+    pub vk_beta_2: Vec<Vec<String>>,
+    pub vk_gamma_2: Vec<Vec<String>>,
+    pub vk_delta_2: Vec<Vec<String>>,
+    #[serde(rename = "IC")]
+    pub ic: Vec<Vec<String>>,
+}
+
+impl PassportVerifyingKey {
+    /// Checks that `proof` is a valid Groth16 proof for `public_signals`.
+    pub fn verify(&self, proof: &Groth16Proof, public_signals: &[String]) -> Result<(), String> {
+        if proof.protocol != "groth16" {
+            return Err(format!(
+                "Passport proof protocol should be groth16 but was {}",
+                proof.protocol
+            ));
+        }
+
+        let n_public = self.ic.len().saturating_sub(1);
+        if public_signals.len() != n_public {
+            return Err(format!(
+                "Passport proof should have {} public signals but had {}",
+                n_public,
+                public_signals.len()
+            ));
+        }
+
+        let verifying_key: VerifyingKeyJson = serde_json::from_value(serde_json::json!({
+            "protocol": "groth16",
+            "curve": "bn128",
+            "nPublic": n_public,
+            "vk_alpha_1": self.vk_alpha_1,
+            "vk_beta_2": self.vk_beta_2,
+            "vk_gamma_2": self.vk_gamma_2,
+            "vk_delta_2": self.vk_delta_2,
+            // Only used by snarkjs to skip a pairing, the verifier does not read it.
+            "vk_alphabeta_12": [],
+            "IC": self.ic,
+        }))
+        .map_err(|e| format!("Invalid passport verification key: {e}"))?;
+
+        let proof: ProofJson = serde_json::from_value(serde_json::json!({
+            "pi_a": proof.a,
+            "pi_b": proof.b,
+            "pi_c": proof.c,
+            "protocol": proof.protocol,
+        }))
+        .map_err(|e| format!("Invalid passport proof: {e}"))?;
+
+        let public_inputs = PublicInputsJson {
+            values: public_signals.to_vec(),
+        };
+
+        Verifier::from_json(proof, public_inputs, verifying_key)
+            .and_then(|verifier| verifier.verify())
+            .map_err(|e| format!("Passport proof verification failed: {e}"))
+    }
+}
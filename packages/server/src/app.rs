@@ -1,4 +1,8 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::Result;
 use axum::{
@@ -19,9 +23,16 @@ use hyle::{
 use hyle_hydentity::HydentityAction;
 use hyle_hyllar::HyllarAction;
 use sdk::ContractAction;
-use ticket_app::TicketAppAction;
+use ticket_app::{
+    event::EventId,
+    frontend_data::{Groth16Proof, PassportData},
+    policy::{check_compliance, COMPLIANCE_ERROR},
+    pricing::{quote, Quote},
+    public_signals::PassportSignals,
+    TicketApp, TicketAppAction,
+};
 
-use sdk::{BlobTransaction, ContractName, TxHash};
+use sdk::{BlobTransaction, ContractName, Hashed, TimestampMs, TxContext, TxHash};
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 use tower_http::cors::{Any, CorsLayer};
@@ -30,12 +41,17 @@ pub struct AppModule {
     bus: AppModuleBusClient,
 }
 
+/// Private inputs of the transactions sent by the app, keyed by tx hash, until the
+/// prover picks them up.
+pub type PrivateInputs = Arc<std::sync::Mutex<HashMap<TxHash, Vec<u8>>>>;
+
 pub struct AppModuleCtx {
     pub common: Arc<CommonRunContext>,
     pub node_client: Arc<NodeApiHttpClient>,
     pub ticket_app_cn: ContractName,
     pub hyllar_cn: ContractName,
     pub hydentity_cn: ContractName,
+    pub private_inputs: PrivateInputs,
 }
 
 #[derive(Debug, Clone)]
@@ -60,6 +76,7 @@ impl Module for AppModule {
             ticket_app_cn: ctx.ticket_app_cn.clone(),
             hyllar_cn: ctx.hyllar_cn.clone(),
             hydentity_cn: ctx.hydentity_cn.clone(),
+            private_inputs: ctx.private_inputs.clone(),
             app: Arc::new(Mutex::new(HyleOofCtx {
                 bus: ctx.common.bus.new_handle(),
             })),
@@ -74,6 +91,8 @@ impl Module for AppModule {
         let api = Router::new()
             .route("/_health", get(health))
             .route("/api/buy", post(buy))
+            .route("/api/send", post(send_with_proof))
+            .route("/api/quote", post(get_quote))
             .route("/api/config", get(get_config))
             .with_state(state)
             .layer(cors);
@@ -104,6 +123,7 @@ struct RouterCtx {
     pub ticket_app_cn: ContractName,
     pub hyllar_cn: ContractName,
     pub hydentity_cn: ContractName,
+    pub private_inputs: PrivateInputs,
 }
 
 pub struct HyleOofCtx {
@@ -187,6 +207,39 @@ async fn buy(
     send(ctx.clone(), auth, body).await
 }
 
+async fn send_with_proof(
+    State(ctx): State<RouterCtx>,
+    Json(body): Json<SendRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    // Only the ticket app reads the proof, any other transaction has no use for this route.
+    if !body
+        .tx
+        .blobs
+        .iter()
+        .any(|blob| blob.contract_name == ctx.ticket_app_cn)
+    {
+        return Err(AppError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Transaction has no {} blob", ctx.ticket_app_cn),
+        ));
+    }
+
+    submit(ctx, body.tx, &body.proof).await
+}
+
+async fn get_quote(
+    State(ctx): State<RouterCtx>,
+    Json(body): Json<QuoteRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let passport = PassportSignals::decode(&body.passport.public_signal)
+        .map_err(|e| AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(e)))?;
+    let token = body.token.unwrap_or_else(|| ctx.hyllar_cn.clone());
+
+    Ok(Json(
+        quote_ticket(&ctx, &body.event_id, &token, &passport).await?,
+    ))
+}
+
 async fn get_config(State(ctx): State<RouterCtx>) -> impl IntoResponse {
     Json(ConfigResponse {
         contract_name: ctx.ticket_app_cn.0,
@@ -203,12 +256,34 @@ async fn send(
     let identity = auth.user.clone();
     tracing::info!("identity:{:?}", identity);
 
+    // Reject malformed passport data before it costs a transaction.
+    let passport = PassportSignals::decode(&body.passport.public_signal)
+        .map_err(|e| AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(e)))?;
+    // Passports hold personal data, only their nullifier is logged.
+    tracing::debug!("passport nullifier:{}", hex::encode(passport.nullifier));
+
+    passport
+        .check_user(&identity.clone().into())
+        .map_err(|e| AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(e)))?;
+    // Charge what the contract will, as of its latest settled state.
+    let token = body.token.clone().unwrap_or_else(|| ctx.hyllar_cn.clone());
+    let quote = quote_ticket(&ctx, &body.event_id, &token, &passport).await?;
+    tracing::info!("quote:{:?}", quote);
+
     let action_ticket_app = TicketAppAction::BuyTicket {
-        nationality: body.nationality,
+        event_id: body.event_id.clone(),
+        public_signals: body.passport.public_signal.clone(),
     };
-    let action_hyllar = HyllarAction::Transfer {
-        recipient: "ticket-app".to_string(),
-        amount: body.price,
+    let action_hyllar = match body.sponsor.clone() {
+        Some(owner) => HyllarAction::TransferFrom {
+            owner,
+            recipient: "ticket-app".to_string(),
+            amount: quote.price,
+        },
+        None => HyllarAction::Transfer {
+            recipient: "ticket-app".to_string(),
+            amount: quote.price,
+        },
     };
     let action_hydentity = HydentityAction::VerifyIdentity {
         account: identity.clone(),
@@ -217,15 +292,61 @@ async fn send(
 
     let blobs = vec![
         action_hydentity.as_blob(ctx.hydentity_cn.clone()),
-        action_hyllar.as_blob(ctx.hyllar_cn.clone(), None, None),
+        action_hyllar.as_blob(token, None, None),
         action_ticket_app.as_blob(ctx.ticket_app_cn.clone()),
     ];
     tracing::info!("blobs:{:?}", blobs);
 
-    let res = ctx
-        .client
-        .send_tx_blob(&BlobTransaction::new(identity.clone(), blobs))
-        .await;
+    let blob_tx = BlobTransaction::new(identity.clone(), blobs);
+
+    submit(ctx, blob_tx, &body.passport.proof).await
+}
+
+/// Price the contract will charge the holder of `passport` for a ticket of `event_id` paid in
+/// `token`, as of its latest settled state. Fails like the contract would for buyers it refuses.
+async fn quote_ticket(
+    ctx: &RouterCtx,
+    event_id: &EventId,
+    token: &ContractName,
+    passport: &PassportSignals,
+) -> Result<Quote, AppError> {
+    let contract = ctx.client.get_contract(&ctx.ticket_app_cn).await?;
+    let ticket_app = TicketApp::decode(&contract.state.0).map_err(|e| anyhow::anyhow!(e))?;
+    let event = ticket_app.events.get(event_id).ok_or_else(|| {
+        AppError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Event {} does not exist", event_id),
+        )
+    })?;
+    let tx_ctx = TxContext {
+        block_height: ctx.client.get_block_height().await?,
+        timestamp: TimestampMs(SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis()),
+        ..Default::default()
+    };
+    ticket_app
+        .check_scope(passport)
+        .map_err(|e| AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(e)))?;
+    check_compliance(&ticket_app.denylist, Some(passport))
+        .map_err(|e| AppError(compliance_status(&e), anyhow::anyhow!(e)))?;
+
+    quote(passport.nationality, event, token, &tx_ctx)
+        .map_err(|e| AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(e)))
+}
+
+/// Sends `blob_tx` and waits for it to be sequenced. The passport proof is only handed to the
+/// prover, it never goes on-chain.
+async fn submit(
+    ctx: RouterCtx,
+    blob_tx: BlobTransaction,
+    proof: &Groth16Proof,
+) -> Result<Json<TxHash>, AppError> {
+    let proof =
+        borsh::to_vec(proof).map_err(|e| AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(e)))?;
+    if let Ok(mut private_inputs) = ctx.private_inputs.lock() {
+        private_inputs.insert(blob_tx.hashed(), proof);
+    }
+
+    let res = ctx.client.send_tx_blob(&blob_tx).await;
 
     if let Err(ref e) = res {
         let root_cause = e.root_cause().to_string();
@@ -253,7 +374,7 @@ async fn send(
                 }
                 AppEvent::FailedTx(sequenced_tx_hash, error) => {
                     if sequenced_tx_hash == tx_hash {
-                        return Err(AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(error)));
+                        return Err(AppError(compliance_status(&error), anyhow::anyhow!(error)));
                     }
                 }
             }
@@ -262,9 +383,41 @@ async fn send(
     .await?
 }
 
+/// Sales refused for compliance reasons are reported as such, other failures as bad requests.
+fn compliance_status(error: &str) -> StatusCode {
+    if error.contains(COMPLIANCE_ERROR) {
+        StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS
+    } else {
+        StatusCode::BAD_REQUEST
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct BuyRequest {
+    event_id: EventId,
     nonce: u32,
-    price: u128,
-    nationality: String,
+    /// Token contract the ticket is paid with, hyllar when missing.
+    #[serde(default)]
+    token: Option<ContractName>,
+    /// Account paying for the ticket, which approved the buyer to spend on its behalf.
+    #[serde(default)]
+    sponsor: Option<String>,
+    passport: PassportData,
+}
+
+#[derive(Debug, Deserialize)]
+struct QuoteRequest {
+    event_id: EventId,
+    /// Token contract the ticket would be paid with, hyllar when missing.
+    #[serde(default)]
+    token: Option<ContractName>,
+    passport: PassportData,
+}
+
+/// Transaction whose ticket app action needs a passport proof, with that proof. The tx
+/// proves its identity on-chain, through its identity contract blob.
+#[derive(Debug, Deserialize)]
+struct SendRequest {
+    tx: BlobTransaction,
+    proof: Groth16Proof,
 }
@@ -15,12 +15,18 @@ use hyle::{
 };
 use prometheus::Registry;
 use prover::{ProverModule, ProverModuleCtx};
-use sdk::{info, ZkContract};
+use sdk::{info, ContractName, ZkContract};
 use std::{
+    collections::BTreeSet,
     env,
     sync::{Arc, Mutex},
 };
-use ticket_app::TicketApp;
+use ticket_app::{
+    event::{parse_price, Event, SaleBound},
+    policy::default_denylist,
+    public_signals::parse_field_element,
+    TicketApp,
+};
 use tracing::error;
 
 mod app;
@@ -44,6 +50,41 @@ pub struct Args {
 
     #[arg(long, default_value = "contract2")]
     pub contract2_cn: String,
+
+    /// Path to the verification key of the passport disclosure circuit
+    #[arg(long)]
+    pub passport_vk: String,
+
+    /// Scope passport proofs are generated for, as a decimal field element
+    #[arg(long, value_parser = parse_field_element)]
+    pub passport_scope: [u8; 32],
+
+    #[arg(long, default_value = "organizer.hydentity")]
+    pub owner: String,
+
+    #[arg(long, default_value = "default")]
+    pub event_id: String,
+
+    #[arg(long, default_value = "10")]
+    pub ticket_price: u128,
+
+    /// Price in another token the tickets can be paid with, as `token=amount`
+    #[arg(long, value_parser = parse_price)]
+    pub extra_price: Vec<(ContractName, u128)>,
+
+    #[arg(long, default_value = "100")]
+    pub capacity: u32,
+
+    #[arg(long)]
+    pub min_age: Option<u8>,
+
+    /// Block height at which ticket sales close
+    #[arg(long)]
+    pub sale_end: Option<u64>,
+
+    /// Block height until which ticket proceeds are held in escrow, not before `sale_end`
+    #[arg(long, requires = "sale_end")]
+    pub escrow_release: Option<u64>,
 }
 
 #[tokio::main]
@@ -66,10 +107,32 @@ async fn main() -> Result<()> {
     let indexer_client =
         Arc::new(IndexerApiHttpClient::new(indexer_url).context("build indexer client")?);
 
+    let mut ticket_app = TicketApp {
+        owner: args.owner.clone().into(),
+        identity_contracts: BTreeSet::from([args.hydentity_cn.clone().into()]),
+        denylist: default_denylist(),
+        ..Default::default()
+    };
+    let prices = std::iter::once((args.hyllar_cn.clone().into(), args.ticket_price))
+        .chain(args.extra_price.clone())
+        .collect();
+    let mut event = Event::new(prices, args.capacity, args.min_age);
+    event.sale_window.end = args.sale_end.map(SaleBound::BlockHeight);
+    event.escrow_release = args.escrow_release.map(SaleBound::BlockHeight);
+    event
+        .check_escrow()
+        .map_err(anyhow::Error::msg)
+        .context("checking escrow release")?;
+    ticket_app.events.insert(args.event_id.clone(), event);
+    let passport_vk = std::fs::read_to_string(&args.passport_vk).context("reading passport vk")?;
+    ticket_app.passport_vk =
+        Some(serde_json::from_str(&passport_vk).context("parsing passport vk")?);
+    ticket_app.passport_scope = Some(args.passport_scope);
+
     let contracts = vec![init::ContractInit {
         name: args.ticket_app_cn.clone().into(),
         program_id: ticket_app::client::tx_executor_handler::metadata::PROGRAM_ID,
-        initial_state: TicketApp::default().commit(),
+        initial_state: ticket_app.commit(),
     }];
 
     match init::init_node(node_client.clone(), indexer_client.clone(), contracts).await {
@@ -98,6 +161,7 @@ async fn main() -> Result<()> {
         ticket_app_cn: args.ticket_app_cn.clone().into(),
         hyllar_cn: args.hyllar_cn.clone().into(),
         hydentity_cn: args.hydentity_cn.clone().into(),
+        private_inputs: Default::default(),
     });
     let start_height = app_ctx.node_client.get_block_height().await?;
     let prover_ctx = Arc::new(ProverModuleCtx {
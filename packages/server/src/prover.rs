@@ -46,7 +46,14 @@ impl Module for ProverModule {
     async fn build(ctx: Self::Context) -> Result<Self> {
         let bus = ProverModuleBusClient::new_from_bus(ctx.app.common.bus.new_handle()).await;
 
-        let ticket_app = TicketApp::default();
+        // Proofs start from the state committed on-chain, which may be in a legacy layout.
+        let contract = ctx
+            .app
+            .node_client
+            .get_contract(&ctx.app.ticket_app_cn)
+            .await
+            .context("fetching ticket app contract")?;
+        let ticket_app = TicketApp::decode(&contract.state.0).map_err(|e| anyhow!(e))?;
         let hydentity = Hydentity::default();
         let hyllar = Hyllar::default();
 
@@ -124,6 +131,9 @@ impl ProverModule {
     }
 
     fn settle_tx(&mut self, tx: TxHash) -> Result<usize> {
+        if let Ok(mut private_inputs) = self.ctx.app.private_inputs.lock() {
+            private_inputs.remove(&tx);
+        }
         let tx = self.unsettled_txs.iter().position(|t| t.hashed() == tx);
         if let Some(pos) = tx {
             self.unsettled_txs.remove(pos);
@@ -295,10 +305,19 @@ impl ProverModule {
 
         let commitment_metadata = state;
 
+        let private_input = self
+            .ctx
+            .app
+            .private_inputs
+            .lock()
+            .ok()
+            .and_then(|private_inputs| private_inputs.get(&tx_hash).cloned())
+            .unwrap_or_default();
+
         let calldata = Calldata {
             identity: tx.identity.clone(),
             tx_hash: tx_hash.clone(),
-            private_input: vec![],
+            private_input,
             blobs: blobs.clone().into(),
             index: *blob_index,
             tx_ctx: Some(tx_ctx.clone()),